@@ -0,0 +1,51 @@
+//! Bridge for reading and writing `binrw` types through vivibin domains
+
+use anyhow::Result;
+use binrw::{BinRead, BinWrite, Endian};
+
+use crate::{AnyReadable, EndianSpecific, HeapCategory, ReadDomain, Reader, SimpleWritable, Writable, WriteCtx, WriteDomain, Writer};
+
+/// Wraps a `binrw` type so it can be used as a field of vivibin types,
+/// using the domain's endianness and the default arguments of the type
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct BinrwAdapter<T>(pub T);
+
+fn binrw_endian(domain: &impl EndianSpecific) -> Endian {
+    if domain.endianness().is_little() {
+        Endian::Little
+    } else {
+        Endian::Big
+    }
+}
+
+impl<T> AnyReadable for BinrwAdapter<T>
+where
+    T: BinRead,
+    for<'a> T::Args<'a>: Default,
+{
+    fn from_reader_any<R: Reader>(reader: &mut R, domain: impl ReadDomain) -> Result<Self> {
+        let value = T::read_options(reader, binrw_endian(&domain), Default::default())?;
+        Ok(BinrwAdapter(value))
+    }
+}
+
+impl<T, D: WriteDomain> SimpleWritable<D> for BinrwAdapter<T>
+where
+    T: BinWrite,
+    for<'a> T::Args<'a>: Default,
+{
+    fn to_writer_simple(&self, writer: &mut impl Writer, domain: &mut D) -> Result<()> {
+        self.0.write_options(writer, binrw_endian(domain), Default::default())?;
+        Ok(())
+    }
+}
+
+impl<T, C: HeapCategory, D: WriteDomain<Cat = C>> Writable<C, D> for BinrwAdapter<T>
+where
+    T: BinWrite,
+    for<'a> T::Args<'a>: Default,
+{
+    fn to_writer_unboxed(&self, ctx: &mut impl WriteCtx<C>, domain: &mut D) -> Result<()> {
+        self.to_writer_simple(ctx.cur_writer(), domain)
+    }
+}
@@ -0,0 +1,37 @@
+//! Checksums commonly found at the end of binary formats, for use with `WriteCtxImpl::to_buffer_checked`
+
+const CRC32_TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut i = 0;
+    
+    while i < 256 {
+        let mut value = i as u32;
+        let mut bit = 0;
+        
+        while bit < 8 {
+            value = if value & 1 != 0 { (value >> 1) ^ 0xEDB88320 } else { value >> 1 };
+            bit += 1;
+        }
+        
+        table[i] = value;
+        i += 1;
+    }
+    
+    table
+};
+
+/// Standard CRC-32 (IEEE 802.3), as used by zlib, PNG and zip
+pub fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    
+    for byte in bytes {
+        crc = (crc >> 8) ^ CRC32_TABLE[((crc ^ *byte as u32) & 0xFF) as usize];
+    }
+    
+    !crc
+}
+
+/// Sum of all bytes, wrapping on overflow
+pub fn sum32(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0u32, |sum, byte| sum.wrapping_add(*byte as u32))
+}
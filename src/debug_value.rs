@@ -0,0 +1,208 @@
+//! A generic representation of parsed values for inspecting them in tools, see `#[derive(VivibinDebug)]`
+
+use alloc::fmt::Write;
+use core::num::{NonZeroI16, NonZeroI32, NonZeroI64, NonZeroI8, NonZeroU16, NonZeroU32, NonZeroU64, NonZeroU8};
+
+use crate::pointers::{PointerNonZero32, PointerZero32, PointerZero64, Ptr};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum DebugValue {
+    Null,
+    Bool(bool),
+    Int(i128),
+    Float(f64),
+    Str(String),
+    Seq(Vec<DebugValue>),
+    Map(Vec<(String, DebugValue)>),
+}
+
+impl DebugValue {
+    /// Formats the value as JSON
+    pub fn to_json(&self) -> String {
+        let mut output = String::new();
+        self.write_json(&mut output);
+        output
+    }
+    
+    fn write_json(&self, output: &mut String) {
+        match self {
+            DebugValue::Null => output.push_str("null"),
+            DebugValue::Bool(value) => write!(output, "{value}").unwrap(),
+            DebugValue::Int(value) => write!(output, "{value}").unwrap(),
+            DebugValue::Float(value) if value.is_finite() => write!(output, "{value}").unwrap(),
+            DebugValue::Float(_) => output.push_str("null"),
+            DebugValue::Str(value) => write_json_str(output, value),
+            DebugValue::Seq(values) => {
+                output.push('[');
+                for (index, value) in values.iter().enumerate() {
+                    if index != 0 {
+                        output.push(',');
+                    }
+                    value.write_json(output);
+                }
+                output.push(']');
+            },
+            DebugValue::Map(entries) => {
+                output.push('{');
+                for (index, (key, value)) in entries.iter().enumerate() {
+                    if index != 0 {
+                        output.push(',');
+                    }
+                    write_json_str(output, key);
+                    output.push(':');
+                    value.write_json(output);
+                }
+                output.push('}');
+            },
+        }
+    }
+}
+
+fn write_json_str(output: &mut String, value: &str) {
+    output.push('"');
+    
+    for c in value.chars() {
+        match c {
+            '"' => output.push_str("\\\""),
+            '\\' => output.push_str("\\\\"),
+            '\n' => output.push_str("\\n"),
+            c if (c as u32) < 0x20 => write!(output, "\\u{:04x}", c as u32).unwrap(),
+            c => output.push(c),
+        }
+    }
+    
+    output.push('"');
+}
+
+pub trait ToDebugValue {
+    fn to_debug_value(&self) -> DebugValue;
+}
+
+macro_rules! impl_debug_value {
+    ($variant:ident, $($type:ty),*) => {
+        $(
+            impl ToDebugValue for $type {
+                fn to_debug_value(&self) -> DebugValue {
+                    DebugValue::$variant((*self).into())
+                }
+            }
+        )*
+    };
+}
+
+/// For types wrapping an integer that can be converted losslessly, e.g. through `get` or `value`
+macro_rules! impl_debug_value_via {
+    ($method:ident, $($type:ty),*) => {
+        $(
+            impl ToDebugValue for $type {
+                fn to_debug_value(&self) -> DebugValue {
+                    DebugValue::Int(self.$method().into())
+                }
+            }
+        )*
+    };
+}
+
+macro_rules! impl_debug_value_tuple {
+    ($($name:ident),+) => {
+        impl<$($name: ToDebugValue),+> ToDebugValue for ($($name,)+) {
+            #[allow(non_snake_case)]
+            fn to_debug_value(&self) -> DebugValue {
+                let ($($name,)+) = self;
+                DebugValue::Seq(vec![$($name.to_debug_value()),+])
+            }
+        }
+    };
+}
+
+impl_debug_value!(Int, u8, u16, u32, u64, i8, i16, i32, i64);
+impl_debug_value!(Float, f32, f64);
+impl_debug_value!(Bool, bool);
+
+impl_debug_value_via!(get, NonZeroU8, NonZeroU16, NonZeroU32, NonZeroU64, NonZeroI8, NonZeroI16, NonZeroI32, NonZeroI64);
+impl_debug_value_via!(value, PointerZero32, PointerZero64, PointerNonZero32);
+
+impl_debug_value_tuple!(T0);
+impl_debug_value_tuple!(T0, T1);
+impl_debug_value_tuple!(T0, T1, T2);
+impl_debug_value_tuple!(T0, T1, T2, T3);
+impl_debug_value_tuple!(T0, T1, T2, T3, T4);
+impl_debug_value_tuple!(T0, T1, T2, T3, T4, T5);
+impl_debug_value_tuple!(T0, T1, T2, T3, T4, T5, T6);
+impl_debug_value_tuple!(T0, T1, T2, T3, T4, T5, T6, T7);
+
+// usize and isize don't implement Into<i128>, but are never wider than 64 bits
+impl ToDebugValue for usize {
+    fn to_debug_value(&self) -> DebugValue {
+        DebugValue::Int(*self as i128)
+    }
+}
+
+impl ToDebugValue for isize {
+    fn to_debug_value(&self) -> DebugValue {
+        DebugValue::Int(*self as i128)
+    }
+}
+
+impl ToDebugValue for () {
+    fn to_debug_value(&self) -> DebugValue {
+        DebugValue::Null
+    }
+}
+
+impl<T> ToDebugValue for Ptr<T> {
+    fn to_debug_value(&self) -> DebugValue {
+        self.pointer().to_debug_value()
+    }
+}
+
+impl ToDebugValue for char {
+    fn to_debug_value(&self) -> DebugValue {
+        DebugValue::Str(self.to_string())
+    }
+}
+
+impl ToDebugValue for str {
+    fn to_debug_value(&self) -> DebugValue {
+        DebugValue::Str(self.to_owned())
+    }
+}
+
+impl ToDebugValue for String {
+    fn to_debug_value(&self) -> DebugValue {
+        DebugValue::Str(self.clone())
+    }
+}
+
+impl<T: ToDebugValue> ToDebugValue for Option<T> {
+    fn to_debug_value(&self) -> DebugValue {
+        match self {
+            Some(value) => value.to_debug_value(),
+            None => DebugValue::Null,
+        }
+    }
+}
+
+impl<T: ToDebugValue + ?Sized> ToDebugValue for Box<T> {
+    fn to_debug_value(&self) -> DebugValue {
+        (**self).to_debug_value()
+    }
+}
+
+impl<T: ToDebugValue> ToDebugValue for [T] {
+    fn to_debug_value(&self) -> DebugValue {
+        DebugValue::Seq(self.iter().map(ToDebugValue::to_debug_value).collect())
+    }
+}
+
+impl<T: ToDebugValue> ToDebugValue for Vec<T> {
+    fn to_debug_value(&self) -> DebugValue {
+        self.as_slice().to_debug_value()
+    }
+}
+
+impl<T: ToDebugValue, const N: usize> ToDebugValue for [T; N] {
+    fn to_debug_value(&self) -> DebugValue {
+        self.as_slice().to_debug_value()
+    }
+}
@@ -1,29 +1,70 @@
-use anyhow::Result;
+use core::fmt;
+use core::num::{NonZeroI16, NonZeroI32, NonZeroI64, NonZeroI8, NonZeroU16, NonZeroU32, NonZeroU64, NonZeroU8};
+use std::io::Write;
 
-use crate::{impl_writable_from_simple, AnyReadable, Endianness, HeapCategory, ReadDomain, ReadableWithArgs, Reader, SimpleWritable, WriteDomain, Writer};
+use anyhow::{anyhow, Context, Result};
+
+use crate::{impl_writable_from_simple, AnyReadable, CanReadVec, CanWriteSlice, FixedSize, HeapCategory, ReadDomain, ReadDomainExt, ReadVecFallbackExt, Readable, ReadableWithArgs, Reader, SimpleWritable, StrEncoding, Writable, WritableWithArgs, WriteCtx, WriteDomain, WriteSliceFallbackExt, Writer};
 
 impl HeapCategory for () {}
 
+// unit, reads and writes nothing
+impl AnyReadable for () {
+    fn from_reader_any<R: Reader>(_reader: &mut R, _domain: impl ReadDomain) -> Result<Self> {
+        Ok(())
+    }
+}
+
+impl<D: WriteDomain> SimpleWritable<D> for () {
+    fn to_writer_simple(&self, _ctx: &mut impl Writer, _domain: &mut D) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl_writable_from_simple!(());
+
+impl FixedSize for () {
+    const SIZE: usize = 0;
+}
+
+/// Appends the position where a failed read stopped to `message`. Only called on the error path,
+/// so that successful reads of primitives don't need to query the position
+fn error_context(reader: &mut impl Reader, message: fmt::Arguments<'_>) -> String {
+    match reader.position() {
+        Ok(offset) => format!("{message} (at offset 0x{offset:x})"),
+        Err(_) => message.to_string(),
+    }
+}
+
 // numbers
 macro_rules! impl_rw_number {
-    ($type:ident, $byte_size:expr) => {
+    ($type:ident, $byte_size:expr, $read_slice:ident, $write_slice:ident) => {
         impl AnyReadable for $type {
             fn from_reader_any<R: Reader>(reader: &mut R, domain: impl ReadDomain) -> Result<Self> {
                 let mut buf = [0; $byte_size];
-                reader.read_exact(&mut buf)?;
-                let result = match domain.endianness() {
-                    Endianness::Little => $type::from_le_bytes(buf),
-                    Endianness::Big => $type::from_be_bytes(buf),
-                };
-                Ok(result)
+                reader.read_exact(&mut buf)
+                    .with_context(|| error_context(reader, format_args!("Could not read {}", stringify!($type))))?;
+                if domain.endianness().is_little() {
+                    Ok($type::from_le_bytes(buf))
+                } else {
+                    Ok($type::from_be_bytes(buf))
+                }
+            }
+            
+            fn from_reader_any_array<R: Reader, const N: usize>(reader: &mut R, domain: impl ReadDomain) -> Result<[Self; N]> {
+                let mut values = [$type::default(); N];
+                reader.$read_slice(&mut values, domain.endianness())
+                    .with_context(|| error_context(reader, format_args!("Could not read [{}; {N}]", stringify!($type))))?;
+                Ok(values)
             }
         }
         
         impl<D: WriteDomain> SimpleWritable<D> for $type {
             fn to_writer_simple(&self, ctx: &mut impl Writer, domain: &mut D) -> Result<()> {
-                let bytes = match domain.endianness() {
-                    Endianness::Little => self.to_le_bytes(),
-                    Endianness::Big => self.to_be_bytes(),
+                let bytes = if domain.endianness().is_little() {
+                    self.to_le_bytes()
+                } else {
+                    self.to_be_bytes()
                 };
                 
                 ctx.write_all(&bytes)?;
@@ -31,32 +72,84 @@ macro_rules! impl_rw_number {
             }
         }
         
-        impl_writable_from_simple!($type);
+        impl<C: HeapCategory, D: WriteDomain<Cat = C>> Writable<C, D> for $type {
+            fn to_writer_unboxed(&self, ctx: &mut impl WriteCtx<C>, domain: &mut D) -> Result<()> {
+                self.to_writer_simple(ctx.cur_writer(), domain)
+            }
+            
+            fn to_writer_slice(values: &[Self], ctx: &mut impl WriteCtx<C>, domain: &mut D) -> Result<()> {
+                ctx.cur_writer().$write_slice(values, domain.endianness())
+            }
+        }
+        
+        impl FixedSize for $type {
+            const SIZE: usize = $byte_size;
+        }
     };
 }
 
 macro_rules! impl_traits_number {
-    ($type:ident, $byte_size:expr) => {
-        impl_rw_number!($type, $byte_size);
+    ($type:ident, $byte_size:expr, $read_slice:ident, $write_slice:ident) => {
+        impl_rw_number!($type, $byte_size, $read_slice, $write_slice);
         
         impl HeapCategory for $type {}
     };
 }
 
-impl_traits_number!(u8, 1);
-impl_traits_number!(u16, 2);
-impl_traits_number!(u32, 4);
-impl_traits_number!(u64, 8);
+impl_traits_number!(u8, 1, read_u8_slice, write_u8_slice);
+impl_traits_number!(u16, 2, read_u16_slice, write_u16_slice);
+impl_traits_number!(u32, 4, read_u32_slice, write_u32_slice);
+impl_traits_number!(u64, 8, read_u64_slice, write_u64_slice);
+
+impl_traits_number!(i8, 1, read_i8_slice, write_i8_slice);
+impl_traits_number!(i16, 2, read_i16_slice, write_i16_slice);
+impl_traits_number!(i32, 4, read_i32_slice, write_i32_slice);
+impl_traits_number!(i64, 8, read_i64_slice, write_i64_slice);
+
+impl_rw_number!(f32, 4, read_f32_slice, write_f32_slice);
+impl_rw_number!(f64, 8, read_f64_slice, write_f64_slice);
+
+// nonzero numbers
+macro_rules! impl_rw_nonzero {
+    ($type:ident, $inner:ident) => {
+        impl AnyReadable for $type {
+            fn from_reader_any<R: Reader>(reader: &mut R, domain: impl ReadDomain) -> Result<Self> {
+                let value = $inner::from_reader_any(reader, domain)?;
+                
+                $type::new(value)
+                    .ok_or_else(|| match reader.position() {
+                        Ok(end) => anyhow!("Expected nonzero {}, got zero (at offset 0x{:x})", stringify!($inner), end - $inner::SIZE as u64),
+                        Err(err) => err,
+                    })
+            }
+        }
+        
+        impl<D: WriteDomain> SimpleWritable<D> for $type {
+            fn to_writer_simple(&self, ctx: &mut impl Writer, domain: &mut D) -> Result<()> {
+                self.get().to_writer_simple(ctx, domain)
+            }
+        }
+        
+        impl_writable_from_simple!($type);
+        
+        impl FixedSize for $type {
+            const SIZE: usize = $inner::SIZE;
+        }
+    };
+}
 
-impl_traits_number!(i8, 1);
-impl_traits_number!(i16, 2);
-impl_traits_number!(i32, 4);
-impl_traits_number!(i64, 8);
+impl_rw_nonzero!(NonZeroU8, u8);
+impl_rw_nonzero!(NonZeroU16, u16);
+impl_rw_nonzero!(NonZeroU32, u32);
+impl_rw_nonzero!(NonZeroU64, u64);
 
-impl_rw_number!(f32, 4);
-impl_rw_number!(f64, 8);
+impl_rw_nonzero!(NonZeroI8, i8);
+impl_rw_nonzero!(NonZeroI16, i16);
+impl_rw_nonzero!(NonZeroI32, i32);
+impl_rw_nonzero!(NonZeroI64, i64);
 
 // booleans
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum BoolSize {
     U8,
     U16,
@@ -64,6 +157,17 @@ pub enum BoolSize {
     U64,
 }
 
+impl BoolSize {
+    pub fn byte_size(self) -> usize {
+        match self {
+            BoolSize::U8 => 1,
+            BoolSize::U16 => 2,
+            BoolSize::U32 => 4,
+            BoolSize::U64 => 8,
+        }
+    }
+}
+
 impl AnyReadable for bool {
     fn from_reader_any<R: Reader>(reader: &mut R, domain: impl ReadDomain) -> Result<Self> {
         Self::from_reader_args(reader, domain, BoolSize::U32)
@@ -81,7 +185,6 @@ impl ReadableWithArgs<BoolSize> for bool {
     }
 }
 
-// TODO: allow specifying size
 impl<D: WriteDomain> SimpleWritable<D> for bool {
     fn to_writer_simple(&self, ctx: &mut impl Writer, domain: &mut D) -> Result<()> {
         u32::from(*self).to_writer_simple(ctx, domain)?;
@@ -91,3 +194,248 @@ impl<D: WriteDomain> SimpleWritable<D> for bool {
 
 impl_writable_from_simple!(bool);
 
+// matches the default BoolSize::U32 used by AnyReadable
+impl FixedSize for bool {
+    const SIZE: usize = 4;
+}
+
+impl<C: HeapCategory, D: WriteDomain<Cat = C>> WritableWithArgs<C, D, BoolSize> for bool {
+    fn to_writer_args(&self, ctx: &mut impl WriteCtx<C>, domain: &mut D, args: BoolSize) -> Result<()> {
+        ctx.write_uint((*self).into(), args.byte_size(), domain.endianness())
+    }
+}
+
+
+// characters
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CharWidth {
+    U8,
+    U16,
+    U32,
+}
+
+impl CharWidth {
+    pub fn byte_size(self) -> usize {
+        match self {
+            CharWidth::U8 => 1,
+            CharWidth::U16 => 2,
+            CharWidth::U32 => 4,
+        }
+    }
+}
+
+impl ReadableWithArgs<CharWidth> for char {
+    fn from_reader_args(reader: &mut impl Reader, domain: impl ReadDomain, args: CharWidth) -> Result<Self> {
+        let offset = reader.position()?;
+        let value = reader.read_uint(args.byte_size(), domain.endianness())? as u32;
+        
+        char::from_u32(value)
+            .ok_or_else(|| anyhow!("Invalid character 0x{value:x} (at offset 0x{offset:x})"))
+    }
+}
+
+impl<C: HeapCategory, D: WriteDomain<Cat = C>> WritableWithArgs<C, D, CharWidth> for char {
+    fn to_writer_args(&self, ctx: &mut impl WriteCtx<C>, domain: &mut D, args: CharWidth) -> Result<()> {
+        ctx.write_uint(u32::from(*self).into(), args.byte_size(), domain.endianness())
+    }
+}
+
+// strings
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PrefixWidth {
+    U8,
+    U16,
+    U32,
+}
+
+impl PrefixWidth {
+    pub fn byte_size(self) -> usize {
+        match self {
+            PrefixWidth::U8 => 1,
+            PrefixWidth::U16 => 2,
+            PrefixWidth::U32 => 4,
+        }
+    }
+}
+
+/// A string stored inline, preceded by its length in bytes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StrLenPrefix {
+    pub width: PrefixWidth,
+    pub encoding: StrEncoding,
+}
+
+impl StrLenPrefix {
+    pub fn new(width: PrefixWidth, encoding: StrEncoding) -> Self {
+        Self { width, encoding }
+    }
+}
+
+impl ReadableWithArgs<StrLenPrefix> for String {
+    fn from_reader_args(reader: &mut impl Reader, domain: impl ReadDomain, args: StrLenPrefix) -> Result<Self> {
+        let offset = reader.position()?;
+        let len = reader.read_uint(args.width.byte_size(), domain.endianness())? as usize;
+        domain.read_limits().check_alloc::<u8>(len, offset)?;
+        reader.read_str_encoded(len, args.encoding)
+    }
+}
+
+impl<C: HeapCategory, D: WriteDomain<Cat = C>> WritableWithArgs<C, D, StrLenPrefix> for String {
+    fn to_writer_args(&self, ctx: &mut impl WriteCtx<C>, domain: &mut D, args: StrLenPrefix) -> Result<()> {
+        let bytes = args.encoding.encode(self)?;
+        let max_len = u64::MAX >> (64 - args.width.byte_size() * 8);
+        
+        if bytes.len() as u64 > max_len {
+            return Err(anyhow!("String of {} bytes is too long for a {:?} length prefix", bytes.len(), args.width));
+        }
+        
+        ctx.write_uint(bytes.len() as u64, args.width.byte_size(), domain.endianness())?;
+        ctx.write_all(&bytes)?;
+        Ok(())
+    }
+}
+
+// options
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OptionMode {
+    /// A bool of the given size in front of the value, which is left out if the bool is false
+    Flag(BoolSize),
+    /// The value is absent if the integer of the given byte size in its place equals the sentinel
+    Sentinel { byte_size: usize, value: u64 },
+}
+
+impl<T: AnyReadable> ReadableWithArgs<OptionMode> for Option<T> {
+    fn from_reader_args(reader: &mut impl Reader, domain: impl ReadDomain, args: OptionMode) -> Result<Self> {
+        match args {
+            OptionMode::Flag(bool_size) => {
+                if bool::from_reader_args(reader, domain, bool_size)? {
+                    Ok(Some(T::from_reader_any(reader, domain)?))
+                } else {
+                    Ok(None)
+                }
+            },
+            OptionMode::Sentinel { byte_size, value } => {
+                if reader.peek_uint(byte_size, domain.endianness())? == value {
+                    reader.read_uint(byte_size, domain.endianness())?;
+                    Ok(None)
+                } else {
+                    Ok(Some(T::from_reader_any(reader, domain)?))
+                }
+            },
+        }
+    }
+}
+
+impl<C: HeapCategory, D: WriteDomain<Cat = C>, T: Writable<C, D>> WritableWithArgs<C, D, OptionMode> for Option<T> {
+    fn to_writer_args(&self, ctx: &mut impl WriteCtx<C>, domain: &mut D, args: OptionMode) -> Result<()> {
+        match args {
+            OptionMode::Flag(bool_size) => {
+                ctx.write_uint(self.is_some().into(), bool_size.byte_size(), domain.endianness())?;
+                
+                if let Some(value) = self {
+                    value.to_writer(ctx, domain)?;
+                }
+            },
+            OptionMode::Sentinel { byte_size, value: sentinel } => {
+                if let Some(value) = self {
+                    value.to_writer(ctx, domain)?;
+                } else {
+                    ctx.write_uint(sentinel, byte_size, domain.endianness())?;
+                }
+            },
+        }
+        Ok(())
+    }
+}
+
+// arrays
+impl<T: FixedSize, const N: usize> FixedSize for [T; N] {
+    const SIZE: usize = T::SIZE * N;
+}
+
+impl<D: ReadDomain, T: Readable<D>, const N: usize> Readable<D> for [T; N] {
+    fn from_reader_unboxed<R: Reader>(reader: &mut R, domain: D) -> Result<Self> {
+        domain.read_array(reader)
+    }
+}
+
+impl<C: HeapCategory, D: WriteDomain<Cat = C>, T: Writable<C, D>, const N: usize> Writable<C, D> for [T; N] {
+    fn to_writer_unboxed(&self, ctx: &mut impl WriteCtx<C>, domain: &mut D) -> Result<()> {
+        T::to_writer_slice(self, ctx, domain)
+    }
+    
+    fn to_writer_unboxed_post(&self, ctx: &mut impl WriteCtx<C>, domain: &mut D) -> Result<()> {
+        for value in self {
+            value.to_writer_post(ctx, domain)?;
+        }
+        Ok(())
+    }
+}
+
+// vecs
+impl<D: CanReadVec, T: Readable<D> + 'static> Readable<D> for Vec<T> {
+    fn from_reader_unboxed<R: Reader>(reader: &mut R, domain: D) -> Result<Self> {
+        domain.read_std_vec_fallback(reader)
+    }
+}
+
+impl<C: HeapCategory, D: CanWriteSlice<C>, T: Writable<C, D> + 'static> Writable<C, D> for Vec<T> {
+    fn to_writer_unboxed(&self, ctx: &mut impl WriteCtx<C>, domain: &mut D) -> Result<()> {
+        domain.write_slice_fallback(ctx, self)
+    }
+}
+
+// boxes, stored inline rather than behind a pointer, which is what #[boxed] is for.
+// Readable can't be implemented for every Box<T> because it would overlap with the AnyReadable impl,
+// the derive macros handle Box<T> fields of other types themselves
+impl<T: AnyReadable> AnyReadable for Box<T> {
+    fn from_reader_any<R: Reader>(reader: &mut R, domain: impl ReadDomain) -> Result<Self> {
+        T::from_reader_any(reader, domain).map(Box::new)
+    }
+}
+
+impl<C: HeapCategory, D: WriteDomain<Cat = C>, T: Writable<C, D>> Writable<C, D> for Box<T> {
+    fn to_writer_unboxed(&self, ctx: &mut impl WriteCtx<C>, domain: &mut D) -> Result<()> {
+        (**self).to_writer(ctx, domain)
+    }
+    
+    fn to_writer_unboxed_post(&self, ctx: &mut impl WriteCtx<C>, domain: &mut D) -> Result<()> {
+        (**self).to_writer_post(ctx, domain)
+    }
+}
+
+// tuples
+macro_rules! impl_rw_tuple {
+    ($($name:ident),+) => {
+        impl<D: ReadDomain, $($name: Readable<D>),+> Readable<D> for ($($name,)+) {
+            fn from_reader_unboxed<R: Reader>(reader: &mut R, domain: D) -> Result<Self> {
+                Ok(($($name::from_reader(reader, domain)?,)+))
+            }
+        }
+        
+        impl<C: HeapCategory, D: WriteDomain<Cat = C>, $($name: Writable<C, D>),+> Writable<C, D> for ($($name,)+) {
+            #[allow(non_snake_case)]
+            fn to_writer_unboxed(&self, ctx: &mut impl WriteCtx<C>, domain: &mut D) -> Result<()> {
+                let ($($name,)+) = self;
+                $($name.to_writer(ctx, domain)?;)+
+                Ok(())
+            }
+            
+            #[allow(non_snake_case)]
+            fn to_writer_unboxed_post(&self, ctx: &mut impl WriteCtx<C>, domain: &mut D) -> Result<()> {
+                let ($($name,)+) = self;
+                $($name.to_writer_post(ctx, domain)?;)+
+                Ok(())
+            }
+        }
+    };
+}
+
+impl_rw_tuple!(T0);
+impl_rw_tuple!(T0, T1);
+impl_rw_tuple!(T0, T1, T2);
+impl_rw_tuple!(T0, T1, T2, T3);
+impl_rw_tuple!(T0, T1, T2, T3, T4);
+impl_rw_tuple!(T0, T1, T2, T3, T4, T5);
+impl_rw_tuple!(T0, T1, T2, T3, T4, T5, T6);
+impl_rw_tuple!(T0, T1, T2, T3, T4, T5, T6, T7);
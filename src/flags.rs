@@ -0,0 +1,129 @@
+//! Helpers for packed flag words, see `define_flags!`
+
+use anyhow::Result;
+
+use crate::{Endianness, Reader, Writer};
+
+/// Reads a `W` byte long flag word
+pub fn read_flags<const W: usize>(reader: &mut impl Reader, endianness: Endianness) -> Result<u64> {
+    reader.read_uint(W, endianness)
+}
+
+/// Assembles a flag word from individual flags
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FlagsBuilder {
+    bits: u64,
+}
+
+impl FlagsBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Sets or clears all bits of `mask`
+    pub fn set(mut self, mask: u64, value: bool) -> Self {
+        if value {
+            self.bits |= mask;
+        } else {
+            self.bits &= !mask;
+        }
+        self
+    }
+    
+    /// Sets bit number `bit`, panicking if it is 64 or more
+    pub fn bit(self, bit: u32, value: bool) -> Self {
+        assert!(bit < u64::BITS, "Flag bit {bit} is out of range for a 64 bit flag word");
+        self.set(1 << bit, value)
+    }
+    
+    pub fn bits(&self) -> u64 {
+        self.bits
+    }
+    
+    /// Writes the flag word as `W` bytes, failing if a set bit doesn't fit
+    pub fn write<const W: usize>(&self, writer: &mut impl Writer, endianness: Endianness) -> Result<()> {
+        writer.write_uint(self.bits, W, endianness)
+    }
+}
+
+/// Defines a newtype over an integer for a set of flags, readable and writable through any domain:
+///
+/// ```ignore
+/// define_flags! {
+///     pub struct MaterialFlags(u16) {
+///         const VISIBLE = 1 << 0;
+///         const TRANSPARENT = 1 << 3;
+///     }
+/// }
+/// ```
+#[macro_export]
+macro_rules! define_flags {
+    (
+        $(#[$meta:meta])*
+        $vis:vis struct $name:ident($int:ty) {
+            $($(#[$flag_meta:meta])* const $flag:ident = $value:expr;)*
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+        $vis struct $name(pub $int);
+        
+        impl $name {
+            $($(#[$flag_meta])* pub const $flag: Self = Self($value);)*
+            
+            pub fn empty() -> Self {
+                Self(0)
+            }
+            
+            pub fn bits(&self) -> $int {
+                self.0
+            }
+            
+            pub fn contains(&self, other: Self) -> bool {
+                self.0 & other.0 == other.0
+            }
+            
+            pub fn insert(&mut self, other: Self) {
+                self.0 |= other.0;
+            }
+            
+            pub fn remove(&mut self, other: Self) {
+                self.0 &= !other.0;
+            }
+            
+            pub fn set(&mut self, other: Self, value: bool) {
+                if value {
+                    self.insert(other);
+                } else {
+                    self.remove(other);
+                }
+            }
+        }
+        
+        impl ::core::ops::BitOr for $name {
+            type Output = Self;
+            
+            fn bitor(self, rhs: Self) -> Self {
+                Self(self.0 | rhs.0)
+            }
+        }
+        
+        impl $crate::AnyReadable for $name {
+            fn from_reader_any<R: $crate::Reader>(reader: &mut R, domain: impl $crate::ReadDomain) -> ::anyhow::Result<Self> {
+                ::core::result::Result::Ok(Self(<$int as $crate::AnyReadable>::from_reader_any(reader, domain)?))
+            }
+        }
+        
+        impl<D: $crate::WriteDomain> $crate::SimpleWritable<D> for $name {
+            fn to_writer_simple(&self, writer: &mut impl $crate::Writer, domain: &mut D) -> ::anyhow::Result<()> {
+                <$int as $crate::SimpleWritable<D>>::to_writer_simple(&self.0, writer, domain)
+            }
+        }
+        
+        impl<C: $crate::HeapCategory, D: $crate::WriteDomain<Cat = C>> $crate::Writable<C, D> for $name {
+            fn to_writer_unboxed(&self, ctx: &mut impl $crate::WriteCtx<C>, domain: &mut D) -> ::anyhow::Result<()> {
+                <Self as $crate::SimpleWritable<D>>::to_writer_simple(self, ctx.cur_writer(), domain)
+            }
+        }
+    };
+}
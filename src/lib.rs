@@ -1,29 +1,68 @@
 extern crate alloc;
 
 use core::{
-    cell::RefCell,
+    cell::Cell,
     cmp::{Eq, Ordering},
     default::Default,
+    fmt::{self, Debug, Formatter},
     hash::Hash,
+    marker::PhantomData,
     mem,
     ops::{Deref, DerefMut},
     str::from_utf8,
 };
 use std::io::{Cursor, Read, Seek, SeekFrom, Write};
 
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, Context, Result};
 use array_init::try_array_init;
+use byteorder::{BigEndian, LittleEndian, ReadBytesExt};
 use indexmap::IndexMap;
+use util::{BuildHasher, HashMap};
 
-use util::HashMap;
-
+#[cfg(feature = "binrw")]
+pub mod binrw_adapter;
+pub mod checksums;
+pub mod debug_value;
 pub mod default_impls;
+pub mod flags;
 pub mod pointers;
+pub mod testing;
 pub mod util;
 
 pub use vivibin_derive::*;
 
 const ZEROES: &[u8] = &[0; 128];
+const READ_CHUNK_SIZE: usize = 0x10000;
+
+macro_rules! fixed_endian_reads {
+    ($($type:ident: $read:ident, $le:ident, $be:ident;)*) => {
+        $(
+            fn $le(&mut self) -> Result<$type> {
+                Ok(ReadBytesExt::$read::<LittleEndian>(self)?)
+            }
+            
+            fn $be(&mut self) -> Result<$type> {
+                Ok(ReadBytesExt::$read::<BigEndian>(self)?)
+            }
+        )*
+    };
+}
+
+macro_rules! batch_reads {
+    ($($type:ident: $read_into:ident, $name:ident;)*) => {
+        $(
+            /// Fills `out` with a single read, which is a lot faster than reading the values one by one
+            fn $name(&mut self, out: &mut [$type], endianness: Endianness) -> Result<()> {
+                if endianness.is_little() {
+                    ReadBytesExt::$read_into::<LittleEndian>(self, out)?;
+                } else {
+                    ReadBytesExt::$read_into::<BigEndian>(self, out)?;
+                }
+                Ok(())
+            }
+        )*
+    };
+}
 
 pub trait Reader: Read + Seek {
     fn position(&mut self) -> Result<u64> {
@@ -35,21 +74,178 @@ pub trait Reader: Read + Seek {
         Ok(())
     }
     
-    fn read_str(&mut self, size: usize) -> Result<String> {
-        let mut bytes = Vec::new();
+    /// Moves `n` bytes forward, or backward if `n` is negative
+    fn skip(&mut self, n: i64) -> Result<()> {
+        self.seek(SeekFrom::Current(n))?;
+        Ok(())
+    }
+    
+    fn rewind_to_start(&mut self) -> Result<()> {
+        self.rewind()?;
+        Ok(())
+    }
+    
+    /// Reads the next N bytes without advancing the stream position
+    fn peek<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let reader = self;
+        scoped_reader_pos!(reader);
         
-        for _ in 0..size {
-            // TODO: consider using domain read method here
-            let mut b: [u8; 1] = [0; 1];
-            self.read_exact(&mut b)?;
+        let mut bytes = [0; N];
+        reader.read_exact(&mut bytes)?;
+        Ok(bytes)
+    }
+    
+    /// Runs `read` and adds what was being read and where it started to its error, e.g. "reading Vec3.x"
+    fn with_offset_context<T>(&mut self, what: &str, read: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        let offset = self.position()?;
+        read(self).with_context(|| format!("Error while {what} (at offset 0x{offset:x})"))
+    }
+    
+    /// Skips ahead to the next multiple of `alignment` in the stream
+    fn align_to(&mut self, alignment: usize) -> Result<()> {
+        if alignment == 0 {
+            return Ok(());
+        }
+        
+        let alignment = alignment as u64;
+        let position = self.position()?;
+        self.set_position(position.next_multiple_of(alignment))
+    }
+    
+    /// Fills `buf` from an absolute offset, restoring the stream position afterwards
+    fn read_exact_at(&mut self, offset: u64, buf: &mut [u8]) -> Result<()> {
+        let reader = self;
+        scoped_reader_pos!(reader);
+        
+        reader.set_position(offset)?;
+        reader.read_exact(buf)?;
+        Ok(())
+    }
+    
+    /// Number of bytes between the current position and the end of the stream
+    fn bytes_remaining(&mut self) -> Result<u64> {
+        let reader = self;
+        scoped_reader_pos!(reader);
+        
+        let position = reader.position()?;
+        let end = reader.seek(SeekFrom::End(0))?;
+        Ok(end.saturating_sub(position))
+    }
+    
+    /// Fails if there is anything left to read
+    fn expect_eof(&mut self) -> Result<()> {
+        let remaining = self.bytes_remaining()?;
+        
+        if remaining != 0 {
+            let offset = self.position()?;
+            return Err(anyhow!("Expected end of data, but 0x{remaining:x} bytes remain (at offset 0x{offset:x})"));
+        }
+        
+        Ok(())
+    }
+    
+    fn read_magic<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let mut magic = [0; N];
+        self.read_exact(&mut magic)?;
+        Ok(magic)
+    }
+    
+    fn expect_magic(&mut self, magic: &[u8]) -> Result<()> {
+        let offset = self.position()?;
+        let actual = self.read_bytes(magic.len())?;
+        
+        if actual != magic {
+            return Err(anyhow!("Bad magic at 0x{offset:x}: expected {magic:?}, got {actual:?}"));
+        }
+        
+        Ok(())
+    }
+    
+    /// Reads an unsigned integer of 1 to 8 bytes, zero-extended into a u64
+    fn read_uint(&mut self, byte_count: usize, endianness: Endianness) -> Result<u64> {
+        if !(1..=8).contains(&byte_count) {
+            return Err(anyhow!("Cannot read integer of {byte_count} bytes, expected 1 to 8"));
+        }
+        
+        let mut buf = [0; 8];
+        
+        if endianness.is_little() {
+            self.read_exact(&mut buf[..byte_count])?;
+            Ok(u64::from_le_bytes(buf))
+        } else {
+            self.read_exact(&mut buf[8 - byte_count..])?;
+            Ok(u64::from_be_bytes(buf))
+        }
+    }
+    
+    fn peek_uint(&mut self, byte_count: usize, endianness: Endianness) -> Result<u64> {
+        let reader = self;
+        scoped_reader_pos!(reader);
+        
+        reader.read_uint(byte_count, endianness)
+    }
+    
+    fn read_u24(&mut self, endianness: Endianness) -> Result<u32> {
+        Ok(self.read_uint(3, endianness)? as u32)
+    }
+    
+    // reads in a fixed endianness, independent of the domain
+    fixed_endian_reads! {
+        u16: read_u16, read_u16_le, read_u16_be;
+        u32: read_u32, read_u32_le, read_u32_be;
+        u64: read_u64, read_u64_le, read_u64_be;
+        i16: read_i16, read_i16_le, read_i16_be;
+        i32: read_i32, read_i32_le, read_i32_be;
+        i64: read_i64, read_i64_le, read_i64_be;
+        f32: read_f32, read_f32_le, read_f32_be;
+        f64: read_f64, read_f64_le, read_f64_be;
+    }
+    
+    fn read_u8_slice(&mut self, out: &mut [u8], _endianness: Endianness) -> Result<()> {
+        self.read_exact(out)?;
+        Ok(())
+    }
+    
+    fn read_i8_slice(&mut self, out: &mut [i8], _endianness: Endianness) -> Result<()> {
+        self.read_i8_into(out)?;
+        Ok(())
+    }
+    
+    batch_reads! {
+        u16: read_u16_into, read_u16_slice;
+        u32: read_u32_into, read_u32_slice;
+        u64: read_u64_into, read_u64_slice;
+        i16: read_i16_into, read_i16_slice;
+        i32: read_i32_into, read_i32_slice;
+        i64: read_i64_into, read_i64_slice;
+        f32: read_f32_into, read_f32_slice;
+        f64: read_f64_into, read_f64_slice;
+    }
+    
+    fn read_bytes(&mut self, size: usize) -> Result<Vec<u8>> {
+        // read in capped chunks so that a corrupt size doesn't allocate gigabytes up front
+        let mut bytes = Vec::with_capacity(size.min(READ_CHUNK_SIZE));
+        let mut remaining = size;
+        
+        while remaining > 0 {
+            let chunk_size = remaining.min(READ_CHUNK_SIZE);
+            let start = bytes.len();
             
-            bytes.push(b[0]);
+            bytes.resize(start + chunk_size, 0);
+            self.read_exact(&mut bytes[start..])?;
+            
+            remaining -= chunk_size;
         }
         
+        Ok(bytes)
+    }
+    
+    fn read_str(&mut self, size: usize) -> Result<String> {
+        let bytes = self.read_bytes(size)?;
         Ok(from_utf8(&bytes)?.to_owned())
     }
     
-    fn read_c_str(&mut self) -> Result<String> {
+    fn read_c_bytes(&mut self) -> Result<Vec<u8>> {
         let mut bytes = Vec::new();
         
         loop {
@@ -64,13 +260,81 @@ pub trait Reader: Read + Seek {
             bytes.push(b[0]);
         }
         
+        Ok(bytes)
+    }
+    
+    fn read_c_str(&mut self) -> Result<String> {
+        let bytes = self.read_c_bytes()?;
         Ok(from_utf8(&bytes)?.to_owned())
     }
+    
+    /// Reads a fixed size string field, cutting it off at the first null byte
+    fn read_fixed_str(&mut self, size: usize) -> Result<String> {
+        let bytes = self.read_bytes(size)?;
+        let length = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+        Ok(from_utf8(&bytes[..length])?.to_owned())
+    }
+    
+    /// Like read_str, but replaces invalid UTF-8 with U+FFFD instead of failing
+    fn read_str_lossy(&mut self, size: usize) -> Result<String> {
+        let bytes = self.read_bytes(size)?;
+        Ok(String::from_utf8_lossy(&bytes).into_owned())
+    }
+    
+    /// Like read_c_str, but replaces invalid UTF-8 with U+FFFD instead of failing
+    fn read_c_str_lossy(&mut self) -> Result<String> {
+        let bytes = self.read_c_bytes()?;
+        Ok(String::from_utf8_lossy(&bytes).into_owned())
+    }
+    
+    fn read_str_encoded(&mut self, size: usize, encoding: StrEncoding) -> Result<String> {
+        let bytes = self.read_bytes(size)?;
+        encoding.decode(&bytes)
+    }
+    
+    fn read_c_str_encoded(&mut self, encoding: StrEncoding) -> Result<String> {
+        let unit_size = encoding.unit_size();
+        let mut bytes = Vec::new();
+        
+        loop {
+            let mut unit: [u8; 2] = [0; 2];
+            let unit = &mut unit[..unit_size];
+            self.read_exact(unit)?;
+            
+            if unit.iter().all(|b| *b == 0) {
+                break;
+            }
+            
+            bytes.extend_from_slice(unit);
+        }
+        
+        encoding.decode(&bytes)
+    }
 }
 
 impl<T: Read + Seek> Reader for T {}
 
-pub trait Writer: Write + Seek + Default {
+macro_rules! batch_writes {
+    ($($type:ident: $name:ident;)*) => {
+        $(
+            /// Serializes `values` into one buffer and writes it all at once
+            fn $name(&mut self, values: &[$type], endianness: Endianness) -> Result<()> {
+                let mut bytes = Vec::with_capacity(size_of_val(values));
+                
+                if endianness.is_little() {
+                    values.iter().for_each(|value| bytes.extend_from_slice(&value.to_le_bytes()));
+                } else {
+                    values.iter().for_each(|value| bytes.extend_from_slice(&value.to_be_bytes()));
+                }
+                
+                self.write_all(&bytes)?;
+                Ok(())
+            }
+        )*
+    };
+}
+
+pub trait Writer: Write + Seek {
     fn position(&mut self) -> Result<u64> {
         Ok(self.stream_position()?)
     }
@@ -90,26 +354,470 @@ pub trait Writer: Write + Seek + Default {
         self.write_all(&[0])?;
         Ok(())
     }
+    
+    /// Writes `buf` at an absolute offset, restoring the stream position afterwards
+    fn write_all_at(&mut self, offset: u64, buf: &[u8]) -> Result<()> {
+        let writer = self;
+        scoped_writer_pos!(writer);
+        
+        writer.set_position(offset)?;
+        writer.write_all(buf)?;
+        Ok(())
+    }
+    
+    /// Writes a string into a fixed size field, padding the rest of it with null bytes
+    fn write_fixed_str(&mut self, string: &str, size: usize) -> Result<()> {
+        self.write_fixed_str_encoded(string, size, StrEncoding::Utf8)
+    }
+    
+    fn write_fixed_str_encoded(&mut self, string: &str, size: usize, encoding: StrEncoding) -> Result<()> {
+        let bytes = encoding.encode(string)?;
+        
+        if bytes.len() > size {
+            return Err(anyhow!("String {string:?} is {} bytes long, which does not fit into field of {size} bytes", bytes.len()));
+        }
+        
+        self.write_all(&bytes)?;
+        write_padding(self, size - bytes.len())
+    }
+    
+    /// Writes an unsigned integer of 1 to 8 bytes, failing if the value doesn't fit
+    fn write_uint(&mut self, value: u64, byte_count: usize, endianness: Endianness) -> Result<()> {
+        if !(1..=8).contains(&byte_count) {
+            return Err(anyhow!("Cannot write integer of {byte_count} bytes, expected 1 to 8"));
+        }
+        
+        if byte_count < 8 && value >> (byte_count * 8) != 0 {
+            return Err(anyhow!("Value 0x{value:x} does not fit into {byte_count} bytes"));
+        }
+        
+        if endianness.is_little() {
+            self.write_all(&value.to_le_bytes()[..byte_count])?;
+        } else {
+            self.write_all(&value.to_be_bytes()[8 - byte_count..])?;
+        }
+        Ok(())
+    }
+    
+    fn write_u24(&mut self, value: u32, endianness: Endianness) -> Result<()> {
+        self.write_uint(value.into(), 3, endianness)
+    }
+    
+    batch_writes! {
+        u8: write_u8_slice;
+        u16: write_u16_slice;
+        u32: write_u32_slice;
+        u64: write_u64_slice;
+        i8: write_i8_slice;
+        i16: write_i16_slice;
+        i32: write_i32_slice;
+        i64: write_i64_slice;
+        f32: write_f32_slice;
+        f64: write_f64_slice;
+    }
+    
+    /// Writes N zero bytes to be filled in later using `fill`
+    fn reserve<const N: usize>(&mut self) -> Result<Placeholder<N>> {
+        let position = self.position()?;
+        self.write_all(&[0; N])?;
+        Ok(Placeholder { position })
+    }
+    
+    fn fill<const N: usize>(&mut self, placeholder: Placeholder<N>, bytes: [u8; N]) -> Result<()> {
+        self.write_all_at(placeholder.position, &bytes)
+    }
+}
+
+/// Location of bytes reserved using `Writer::reserve`
+#[must_use]
+#[derive(Debug, PartialEq, Eq)]
+pub struct Placeholder<const N: usize> {
+    position: u64,
+}
+
+impl<const N: usize> Placeholder<N> {
+    pub fn position(&self) -> u64 {
+        self.position
+    }
 }
 
-impl<T: Write + Seek + Default> Writer for T {}
+impl<T: Write + Seek> Writer for T {}
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Endianness {
     Little,
     Big,
-    // Native?
+    Native,
+}
+
+impl Endianness {
+    /// Maps Native to the endianness of the compile target, leaves Little and Big as is
+    pub fn resolve(self) -> Endianness {
+        match self {
+            Endianness::Native if cfg!(target_endian = "big") => Endianness::Big,
+            Endianness::Native => Endianness::Little,
+            endianness => endianness,
+        }
+    }
+    
+    /// Whether this is little endian, or native on a little endian target
+    pub fn is_little(self) -> bool {
+        match self {
+            Endianness::Little => true,
+            Endianness::Big => false,
+            Endianness::Native => cfg!(target_endian = "little"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StrEncoding {
+    Utf8,
+    /// Requires the `shift-jis` feature, decoding and encoding fail without it
+    ShiftJis,
+    Utf16Le,
+    Utf16Be,
+}
+
+impl StrEncoding {
+    /// Size of a single code unit in bytes, which is also the size of the null terminator
+    pub fn unit_size(self) -> usize {
+        match self {
+            StrEncoding::Utf8 | StrEncoding::ShiftJis => 1,
+            StrEncoding::Utf16Le | StrEncoding::Utf16Be => 2,
+        }
+    }
+    
+    pub fn decode(self, bytes: &[u8]) -> Result<String> {
+        match self {
+            StrEncoding::Utf8 => Ok(from_utf8(bytes)?.to_owned()),
+            #[cfg(feature = "shift-jis")]
+            StrEncoding::ShiftJis => {
+                let string = encoding_rs::SHIFT_JIS
+                    .decode_without_bom_handling_and_without_replacement(bytes)
+                    .ok_or_else(|| anyhow!("Invalid Shift-JIS string {bytes:x?}"))?;
+                Ok(string.into_owned())
+            },
+            #[cfg(not(feature = "shift-jis"))]
+            StrEncoding::ShiftJis => Err(anyhow!("Decoding Shift-JIS strings requires the shift-jis feature")),
+            StrEncoding::Utf16Le | StrEncoding::Utf16Be => {
+                if !bytes.len().is_multiple_of(2) {
+                    return Err(anyhow!("UTF-16 string has odd byte length {}", bytes.len()));
+                }
+                
+                let units: Vec<u16> = bytes.chunks_exact(2)
+                    .map(|pair| match self {
+                        StrEncoding::Utf16Be => u16::from_be_bytes([pair[0], pair[1]]),
+                        _ => u16::from_le_bytes([pair[0], pair[1]]),
+                    })
+                    .collect();
+                
+                Ok(String::from_utf16(&units)?)
+            },
+        }
+    }
+    
+    pub fn encode(self, string: &str) -> Result<Vec<u8>> {
+        match self {
+            StrEncoding::Utf8 => Ok(string.as_bytes().to_vec()),
+            #[cfg(feature = "shift-jis")]
+            StrEncoding::ShiftJis => {
+                let (bytes, _, had_errors) = encoding_rs::SHIFT_JIS.encode(string);
+                
+                if had_errors {
+                    return Err(anyhow!("String {string:?} cannot be represented in Shift-JIS"));
+                }
+                
+                Ok(bytes.into_owned())
+            },
+            #[cfg(not(feature = "shift-jis"))]
+            StrEncoding::ShiftJis => Err(anyhow!("Encoding Shift-JIS strings requires the shift-jis feature")),
+            StrEncoding::Utf16Le => Ok(string.encode_utf16().flat_map(u16::to_le_bytes).collect()),
+            StrEncoding::Utf16Be => Ok(string.encode_utf16().flat_map(u16::to_be_bytes).collect()),
+        }
+    }
 }
 
 pub trait EndianSpecific {
     fn endianness(&self) -> Endianness;
+    
+    /// Changes the byte order the domain reads and writes its own values in, e.g. pointers and counts.
+    /// `EndianAware` calls this to pass its override on, the default ignores it
+    fn set_endianness(&mut self, _endianness: Endianness) {}
+}
+
+/// Domain wrapper that overrides the endianness of another domain, for formats that
+/// store a byte order mark in their header.
+///
+/// Values read and written through `EndianSpecific::endianness` use the override directly.
+/// Pointers, counts and `CanRead`/`CanWrite` values are handled by the inner domain, so they only
+/// follow the override if it implements `EndianSpecific::set_endianness`. Pointers are patched
+/// when the output is resolved, by the domain passed to `to_buffer`, which has to be wrapped as well
+#[derive(Clone, Copy)]
+pub struct EndianAware<D> {
+    pub inner: D,
+    pub endian: Endianness,
+}
+
+impl<D> EndianAware<D> {
+    pub fn new(inner: D, endian: Endianness) -> Self {
+        Self { inner, endian }
+    }
+    
+    /// Reads a 0xFEFF byte order mark and picks the endianness it was written in
+    pub fn from_bom(reader: &mut impl Reader, inner: D) -> Result<Self> {
+        let offset = reader.position()?;
+        let endian = match reader.read_magic()? {
+            [0xFF, 0xFE] => Endianness::Little,
+            [0xFE, 0xFF] => Endianness::Big,
+            bom => return Err(anyhow!("Invalid byte order mark {bom:x?} at 0x{offset:x}")),
+        };
+        
+        Ok(Self { inner, endian })
+    }
+}
+
+impl<D> EndianSpecific for EndianAware<D> {
+    fn endianness(&self) -> Endianness {
+        self.endian
+    }
+    
+    fn set_endianness(&mut self, endianness: Endianness) {
+        self.endian = endianness;
+    }
+}
+
+impl<D: EndianSpecific + Copy> EndianAware<D> {
+    /// Copy of the inner domain with the override applied
+    fn overridden(self) -> D {
+        let mut inner = self.inner;
+        inner.set_endianness(self.endian);
+        inner
+    }
+}
+
+impl<D: EndianSpecific> EndianAware<D> {
+    /// Runs `f` on the inner domain with the override applied, restoring its own endianness afterwards
+    fn with_overridden<T>(&mut self, f: impl FnOnce(&mut D) -> T) -> T {
+        let previous = self.inner.endianness();
+        self.inner.set_endianness(self.endian);
+        let result = f(&mut self.inner);
+        self.inner.set_endianness(previous);
+        result
+    }
+}
+
+impl<D: ReadDomain> ReadDomain for EndianAware<D> {
+    type Pointer = D::Pointer;
+    
+    fn read_box_nullable<T, R: Reader>(self, reader: &mut R, read_content: impl FnOnce(&mut R) -> Result<T>) -> Result<Option<T>> {
+        self.overridden().read_box_nullable(reader, read_content)
+    }
+    
+    fn read_limits(&self) -> ReadLimits {
+        self.inner.read_limits()
+    }
+}
+
+impl<D: CanReadVec> CanReadVec for EndianAware<D> {
+    fn read_std_vec_of<T: 'static, R: Reader>(self, reader: &mut R, read_content: impl Fn(&mut R) -> Result<T>) -> Result<Vec<T>> {
+        self.overridden().read_std_vec_of(reader, read_content)
+    }
+}
+
+impl<T: 'static, D: CanRead<T>> CanRead<T> for EndianAware<D> {
+    fn read(self, reader: &mut impl Reader) -> Result<T> {
+        self.overridden().read(reader)
+    }
+}
+
+impl<D: WriteDomain> WriteDomain for EndianAware<D> {
+    type Pointer = D::Pointer;
+    type Cat = D::Cat;
+    
+    const POINTER_SIZE: usize = D::POINTER_SIZE;
+    
+    fn apply_reference(&mut self, writer: &mut impl Writer, heap_offset: usize, byte_size: usize) -> Result<()> {
+        self.with_overridden(|inner| inner.apply_reference(writer, heap_offset, byte_size))
+    }
+    
+    fn file_alignment(&self) -> usize {
+        self.inner.file_alignment()
+    }
+}
+
+impl<D: Clone> EndianAware<D> {
+    /// The callbacks of write domains get `&mut Self`, which can't be rebuilt around the `&mut D`
+    /// the inner domain hands out, so this runs `f` on a wrapped copy and stores its changes back afterwards
+    fn rewrap<T>(inner: &mut D, endian: Endianness, f: impl FnOnce(&mut Self) -> T) -> T {
+        let mut domain = Self::new(inner.clone(), endian);
+        let result = f(&mut domain);
+        *inner = domain.inner;
+        result
+    }
+}
+
+impl<C: HeapCategory, D: CanWriteSlice<C> + Clone> CanWriteSlice<C> for EndianAware<D> {
+    fn write_slice_of<T: 'static, W: WriteCtx<C>>(
+        &mut self,
+        ctx: &mut W,
+        values: &[T],
+        write_content: impl Fn(&mut Self, &mut W::InnerCtx<'_>, &T) -> Result<()>,
+    ) -> Result<()> {
+        let endian = self.endian;
+        self.with_overridden(|inner| inner.write_slice_of(ctx, values, |inner, ctx, value| {
+            Self::rewrap(inner, endian, |domain| write_content(domain, ctx, value))
+        }))
+    }
+    
+    fn write_slice_aligned_of<T: 'static, W: WriteCtx<C>>(
+        &mut self,
+        ctx: &mut W,
+        values: &[T],
+        element_alignment: usize,
+        pad_end: bool,
+        write_content: impl Fn(&mut Self, &mut W::InnerCtx<'_>, &T) -> Result<()>,
+    ) -> Result<()> {
+        let endian = self.endian;
+        self.with_overridden(|inner| inner.write_slice_aligned_of(ctx, values, element_alignment, pad_end, |inner, ctx, value| {
+            Self::rewrap(inner, endian, |domain| write_content(domain, ctx, value))
+        }))
+    }
+}
+
+impl<C: HeapCategory, D: CanWriteBox<C> + Clone> CanWriteBox<C> for EndianAware<D> {
+    fn write_box_of<W: WriteCtx<C>>(
+        &mut self,
+        ctx: &mut W,
+        write_content: impl FnOnce(&mut Self, &mut W::InnerCtx<'_>) -> Result<()>,
+    ) -> Result<()> {
+        let endian = self.endian;
+        self.with_overridden(|inner| inner.write_box_of(ctx, |inner, ctx| {
+            Self::rewrap(inner, endian, |domain| write_content(domain, ctx))
+        }))
+    }
+    
+    fn write_box_null(&mut self, ctx: &mut impl WriteCtx<C>) -> Result<()> {
+        self.with_overridden(|inner| inner.write_box_null(ctx))
+    }
+}
+
+impl<C: HeapCategory, T: 'static + ?Sized, D: CanWrite<C, T>> CanWrite<C, T> for EndianAware<D> {
+    fn write(&mut self, ctx: &mut impl WriteCtx<C>, value: &T) -> Result<()> {
+        self.with_overridden(|inner| inner.write(ctx, value))
+    }
+    
+    fn write_post(&mut self, ctx: &mut impl WriteCtx<C>, value: &T) -> Result<()> {
+        self.with_overridden(|inner| inner.write_post(ctx, value))
+    }
+}
+
+// lets wrappers like EndianAware borrow a write domain instead of taking ownership of it
+impl<D: EndianSpecific> EndianSpecific for &mut D {
+    fn endianness(&self) -> Endianness {
+        (**self).endianness()
+    }
+    
+    fn set_endianness(&mut self, endianness: Endianness) {
+        (**self).set_endianness(endianness);
+    }
+}
+
+impl<D: WriteDomain> WriteDomain for &mut D {
+    type Pointer = D::Pointer;
+    type Cat = D::Cat;
+    
+    const POINTER_SIZE: usize = D::POINTER_SIZE;
+    
+    fn apply_reference(&mut self, writer: &mut impl Writer, heap_offset: usize, byte_size: usize) -> Result<()> {
+        (**self).apply_reference(writer, heap_offset, byte_size)
+    }
+    
+    fn file_alignment(&self) -> usize {
+        (**self).file_alignment()
+    }
+}
+
+impl<C: HeapCategory, T: 'static + ?Sized, D: CanWrite<C, T>> CanWrite<C, T> for &mut D {
+    fn write(&mut self, ctx: &mut impl WriteCtx<C>, value: &T) -> Result<()> {
+        (**self).write(ctx, value)
+    }
+    
+    fn write_post(&mut self, ctx: &mut impl WriteCtx<C>, value: &T) -> Result<()> {
+        (**self).write_post(ctx, value)
+    }
 }
 
 // reading / parsing
+/// Upper bounds for allocations whose size comes from the file being read,
+/// so that corrupt or malicious input errors out instead of exhausting memory
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ReadLimits {
+    pub max_alloc: usize,
+}
+
+impl ReadLimits {
+    pub const UNLIMITED: Self = Self { max_alloc: usize::MAX };
+    
+    pub fn new(max_alloc: usize) -> Self {
+        Self { max_alloc }
+    }
+    
+    /// Fails if allocating `count` elements of type `T` would exceed `max_alloc` bytes
+    pub fn check_alloc<T>(self, count: usize, offset: u64) -> Result<()> {
+        // zero sized types still cost time per element, so count them as one byte each
+        let size = count.saturating_mul(size_of::<T>().max(1));
+        
+        if size > self.max_alloc {
+            return Err(anyhow!("Allocation of {size} bytes exceeds the limit of {} bytes (at offset 0x{offset:x})", self.max_alloc));
+        }
+        
+        Ok(())
+    }
+}
+
+impl Default for ReadLimits {
+    fn default() -> Self {
+        Self::UNLIMITED
+    }
+}
+
 pub trait ReadDomain: Copy + EndianSpecific {
     type Pointer;
     
     // TODO: make this optional to implement? i. e. split them into another Trait
     fn read_box_nullable<T, R: Reader>(self, reader: &mut R, read_content: impl FnOnce(&mut R) -> Result<T>) -> Result<Option<T>>;
+    
+    /// Limits applied to allocations sized by values from the file. Unlimited by default,
+    /// domains parsing untrusted input should override this
+    fn read_limits(&self) -> ReadLimits {
+        ReadLimits::default()
+    }
+}
+
+/// Domains that know which version of a format they're handling, e.g. from a file header.
+/// This is its own trait because associated types can't have defaults yet.
+/// Derived impls can use it through the `domain` variable, e.g. `#[read_if(domain.version() >= 2)]`
+pub trait VersionedDomain {
+    type Version: Copy;
+    
+    fn version(&self) -> Self::Version;
+}
+
+impl<D: VersionedDomain> VersionedDomain for EndianAware<D> {
+    type Version = D::Version;
+    
+    fn version(&self) -> Self::Version {
+        self.inner.version()
+    }
+}
+
+impl<D: VersionedDomain> VersionedDomain for &mut D {
+    type Version = D::Version;
+    
+    fn version(&self) -> Self::Version {
+        (**self).version()
+    }
 }
 
 pub trait ReadDomainExt: ReadDomain {
@@ -120,6 +828,44 @@ pub trait ReadDomainExt: ReadDomain {
         Ok(value)
     }
     
+    /// Reads an absolute pointer and the value it points to, or `None` if it is zero.
+    /// Can be used to implement `read_box_nullable` for domains with absolute pointers
+    fn read_box_at_absolute<T, R: Reader>(self, reader: &mut R, read_content: impl FnOnce(&mut R) -> Result<T>) -> Result<Option<T>>
+    where
+        Self::Pointer: Readable<Self> + Into<u64>,
+    {
+        let pointer: u64 = Self::Pointer::from_reader(reader, self)?.into();
+        
+        if pointer == 0 {
+            return Ok(None);
+        }
+        
+        scoped_reader_pos!(reader);
+        reader.set_position(pointer)?;
+        Ok(Some(read_content(reader)?))
+    }
+    
+    /// Reads a value at the absolute offset `pointer` and returns to the current position afterwards
+    fn read_at<T, R: Reader>(self, reader: &mut R, pointer: Self::Pointer, read_content: impl FnOnce(&mut R) -> Result<T>) -> Result<T>
+    where
+        Self::Pointer: Into<u64>,
+    {
+        scoped_reader_pos!(reader);
+        reader.set_position(pointer)?;
+        read_content(reader)
+    }
+    
+    /// Reads the `index`th record of a packed table starting at `base` and returns to the current position afterwards
+    fn read_record_at<T: Readable<Self> + FixedSize>(self, reader: &mut impl Reader, base: u64, index: usize) -> Result<T> {
+        let position = index.checked_mul(T::SIZE)
+            .and_then(|offset| base.checked_add(offset as u64))
+            .ok_or_else(|| anyhow!("Record {index} of size 0x{:x} at base 0x{base:x} is out of range", T::SIZE))?;
+        
+        scoped_reader_pos!(reader);
+        reader.set_position(position)?;
+        T::from_reader(reader, self)
+    }
+    
     fn read_std_box_of<T, R: Reader>(self, reader: &mut R, read_content: impl Fn(&mut R) -> Result<T>) -> Result<Box<T>> {
         self.read_box(reader, read_content).map(Box::new)
     }
@@ -128,12 +874,145 @@ pub trait ReadDomainExt: ReadDomain {
         self.read_std_box_of(reader, |reader| T::from_reader(reader, self))
     }
     
+    /// Reads a value that needs extra arguments, like a `bool` with a given `BoolSize`
+    fn read_fallback_args<T: ReadableWithArgs<A>, A>(self, reader: &mut impl Reader, args: A) -> Result<T> {
+        T::from_reader_args(reader, self, args)
+    }
+    
     fn read_unk_array<T, R: Reader, const N: usize>(self, reader: &mut R, read_content: impl Fn(&mut R) -> Result<T>) -> Result<[T; N]> {
         try_array_init(|_| read_content(reader))
     }
     
     fn read_array<T: Readable<Self>, R: Reader, const N: usize>(self, reader: &mut R) -> Result<[T; N]> {
-        try_array_init(|_| T::from_reader(reader, self))
+        T::from_reader_array(reader, self)
+    }
+    
+    /// Like `Reader::read_str`, but checks `size` against the domain's `ReadLimits` first
+    fn read_str_limited(self, reader: &mut impl Reader, size: usize) -> Result<String> {
+        let offset = reader.position()?;
+        self.read_limits().check_alloc::<u8>(size, offset)?;
+        reader.read_str(size)
+    }
+    
+    /// Reads a `W` byte long element count followed by that many elements
+    fn read_len_prefixed_vec_of<const W: usize, T, R: Reader>(self, reader: &mut R, read_content: impl Fn(&mut R) -> Result<T>) -> Result<Vec<T>> {
+        let offset = reader.position()?;
+        let count = reader.read_uint(W, self.endianness())?;
+        
+        if count == 0 {
+            return Ok(Vec::new());
+        }
+        
+        let count = usize::try_from(count).ok()
+            .filter(|count| count.checked_mul(size_of::<T>()).is_some())
+            .ok_or_else(|| anyhow!("Element count {count} is too large (at offset 0x{offset:x})"))?;
+        self.read_limits().check_alloc::<T>(count, offset)?;
+        
+        // don't trust the count for preallocation, it might come from a corrupted file
+        let mut values = Vec::with_capacity(count.min(READ_CHUNK_SIZE));
+        
+        for _ in 0..count {
+            values.push(read_content(reader)?);
+        }
+        
+        Ok(values)
+    }
+    
+    /// Reads a u32 element count followed by a pointer to that many elements,
+    /// where the pointer may be null if there are no elements
+    fn read_count_ptr_array<T: Readable<Self>, R: Reader>(self, reader: &mut R) -> Result<Vec<T>> {
+        let offset = reader.position()?;
+        let count = u32::from_reader(reader, self)?;
+        self.read_limits().check_alloc::<T>(count as usize, offset)?;
+        
+        let values = self.read_box_nullable(reader, |reader| {
+            let mut values = Vec::with_capacity((count as usize).min(READ_CHUNK_SIZE));
+            
+            for _ in 0..count {
+                values.push(T::from_reader(reader, self)?);
+            }
+            
+            Ok(values)
+        })?;
+        
+        match values {
+            Some(values) => Ok(values),
+            None if count == 0 => Ok(Vec::new()),
+            None => Err(anyhow!("Expected pointer to {count} elements, got null (at offset 0x{offset:x})")),
+        }
+    }
+    
+    /// Reads `count` records that each start with a `W` byte long type tag. The tag is only peeked,
+    /// the parser returned by `dispatch` for it reads the whole record including the tag
+    fn read_tagged_vec<T, R: Reader, const W: usize>(self, reader: &mut R, count: usize, dispatch: impl Fn(u64) -> Option<fn(&mut R, Self) -> Result<T>>) -> Result<Vec<T>> {
+        self.read_limits().check_alloc::<T>(count, reader.position()?)?;
+        let mut values = Vec::with_capacity(count.min(READ_CHUNK_SIZE));
+        
+        for _ in 0..count {
+            let offset = reader.position()?;
+            let tag = reader.read_uint(W, self.endianness())?;
+            reader.set_position(offset)?;
+            
+            let read_content = dispatch(tag)
+                .ok_or_else(|| anyhow!("Unknown record tag 0x{tag:x} (at offset 0x{offset:x})"))?;
+            values.push(read_content(reader, self)?);
+        }
+        
+        Ok(values)
+    }
+    
+    /// Reads a `W` byte long entry count followed by that many key/value pairs
+    fn read_len_prefixed_map<const W: usize, K: Eq + Hash, V, R: Reader>(self, reader: &mut R, read_entry: impl Fn(&mut R) -> Result<(K, V)>) -> Result<HashMap<K, V>> {
+        let entries = self.read_len_prefixed_vec_of::<W, _, _>(reader, read_entry)?;
+        Ok(entries.into_iter().collect())
+    }
+    
+    /// Reads elements until the end of the reader, failing if the last element is cut off
+    /// or if an element doesn't consume any bytes, which would never reach the end
+    fn read_until_eof<T: Readable<Self>, R: Reader>(self, reader: &mut R) -> Result<Vec<T>> {
+        let limits = self.read_limits();
+        let mut values = Vec::new();
+        
+        while reader.bytes_remaining()? > 0 {
+            let offset = reader.position()?;
+            limits.check_alloc::<T>(values.len() + 1, offset)?;
+            values.push(T::from_reader(reader, self)?);
+            
+            if reader.position()? == offset {
+                return Err(anyhow!("Element of read_until_eof didn't consume any bytes (at offset 0x{offset:x})"));
+            }
+        }
+        
+        Ok(values)
+    }
+    
+    /// Reads elements until `stop` returns true for one. That sentinel element is consumed but not returned
+    fn read_until<T: Readable<Self>, R: Reader>(self, reader: &mut R, stop: impl Fn(&T) -> bool) -> Result<Vec<T>> {
+        let limits = self.read_limits();
+        let mut values = Vec::new();
+        
+        loop {
+            let offset = reader.position()?;
+            let value = T::from_reader(reader, self)?;
+            
+            if stop(&value) {
+                break;
+            }
+            
+            limits.check_alloc::<T>(values.len() + 1, offset)?;
+            values.push(value);
+        }
+        
+        Ok(values)
+    }
+    
+    /// Reads a `W` byte long discriminant and converts it using `map`, failing on unknown values
+    fn read_enum<const W: usize, T, R: Reader>(self, reader: &mut R, map: impl Fn(u64) -> Option<T>) -> Result<T> {
+        let offset = reader.position()?;
+        let discriminant = reader.read_uint(W, self.endianness())?;
+        
+        map(discriminant)
+            .ok_or_else(|| anyhow!("Unknown discriminant {discriminant} (at offset 0x{offset:x})"))
     }
 }
 
@@ -163,16 +1042,65 @@ pub trait ReadVecExt: CanReadVec {
 
 impl<D: CanReadVec> ReadVecExt for D {}
 
+pub trait CanReadMap: ReadDomain {
+    fn read_map_of<K: Eq + Hash + 'static, V: 'static, R: Reader>(self, reader: &mut R, read_entry: impl Fn(&mut R) -> Result<(K, V)>) -> Result<HashMap<K, V>>;
+}
+
+pub trait ReadMapFallbackExt: CanReadMap {
+    fn read_map_fallback<K: Readable<Self> + Eq + Hash + 'static, V: Readable<Self> + 'static, R: Reader>(self, reader: &mut R) -> Result<HashMap<K, V>> {
+        self.read_map_of(reader, |reader| Ok((K::from_reader(reader, self)?, V::from_reader(reader, self)?)))
+    }
+}
+
+impl<D: CanReadMap> ReadMapFallbackExt for D {}
+
 pub trait CanRead<T: 'static>: ReadDomain {
     fn read(self, reader: &mut impl Reader) -> Result<T>;
 }
 
+/// Implements `CanRead` for several types at once:
+/// `impl_can_read!(impl [T: Bound] Domain<T> { Type => (domain, reader) expr, ... })`
+#[macro_export]
+macro_rules! impl_can_read {
+    (@single [$($generic:tt)*], $domain_type:ty, $type:ty, $domain:ident, $reader:ident, $body:expr) => {
+        impl<$($generic)*> $crate::CanRead<$type> for $domain_type {
+            fn read(self, reader: &mut impl $crate::Reader) -> ::anyhow::Result<$type> {
+                let $domain = self;
+                let $reader = reader;
+                $body
+            }
+        }
+    };
+    (impl $generics:tt $domain_type:ty { $($type:ty => ($domain:ident, $reader:ident) $body:expr),* $(,)? }) => {
+        $(
+            $crate::impl_can_read!(@single $generics, $domain_type, $type, $domain, $reader, $body);
+        )*
+    };
+    ($domain_type:ty { $($type:ty => ($domain:ident, $reader:ident) $body:expr),* $(,)? }) => {
+        $(
+            $crate::impl_can_read!(@single [], $domain_type, $type, $domain, $reader, $body);
+        )*
+    };
+}
+
 pub trait Readable<D: ReadDomain>: Sized {
+    /// Set this if the type is stored behind a pointer by default, which makes `from_reader` go through `read_box`
+    const BOXED: bool = false;
+    
     fn from_reader_unboxed<R: Reader>(reader: &mut R, domain: D) -> Result<Self>;
     
-    /// Override this with a read_box if this type should be boxed by default
+    /// Reads the value behind a pointer if `BOXED` is set, or in place otherwise
     fn from_reader<R: Reader>(reader: &mut R, domain: D) -> Result<Self> {
-        Self::from_reader_unboxed(reader, domain)
+        if Self::BOXED {
+            domain.read_box(reader, |reader| Self::from_reader_unboxed(reader, domain))
+        } else {
+            Self::from_reader_unboxed(reader, domain)
+        }
+    }
+    
+    /// Reads N values in a row, can be overridden to read them all at once
+    fn from_reader_array<R: Reader, const N: usize>(reader: &mut R, domain: D) -> Result<[Self; N]> {
+        try_array_init(|_| Self::from_reader(reader, domain))
     }
 }
 
@@ -183,16 +1111,31 @@ pub trait ReadableWithArgs<T>: Sized {
 // Convenience trait for manual impls of types that are readable by all domains
 pub trait AnyReadable: Sized {
     fn from_reader_any<R: Reader>(reader: &mut R, domain: impl ReadDomain) -> Result<Self>;
+    
+    fn from_reader_any_array<R: Reader, const N: usize>(reader: &mut R, domain: impl ReadDomain) -> Result<[Self; N]> {
+        try_array_init(|_| Self::from_reader_any(reader, domain))
+    }
+}
+
+/// Types that always take up the same amount of bytes, independent of the domain
+pub trait FixedSize {
+    const SIZE: usize;
 }
 
 impl<A: AnyReadable, D: ReadDomain> Readable<D> for A {
     fn from_reader_unboxed<R: Reader>(reader: &mut R, domain: D) -> Result<Self> {
         A::from_reader_any(reader, domain)
     }
+    
+    fn from_reader_array<R: Reader, const N: usize>(reader: &mut R, domain: D) -> Result<[Self; N]> {
+        A::from_reader_any_array(reader, domain)
+    }
 }
 
 // writing / serializing
-pub trait HeapCategory: Eq + Hash + Ord + Default + Clone {}
+/// Categories have to be `'static` because `WriteCtx::InnerCtx` is used for any borrow lifetime
+/// (see the callbacks of `WriteCtx::allocate_next_block`), which the heaps holding them have to outlive
+pub trait HeapCategory: Eq + Hash + Ord + Default + Clone + 'static {}
 
 // TODO: does this have to be sized?
 pub trait WriteDomain: Sized + EndianSpecific {
@@ -200,7 +1143,16 @@ pub trait WriteDomain: Sized + EndianSpecific {
     type Pointer;
     type Cat: HeapCategory;
     
-    fn apply_reference(&mut self, writer: &mut impl Writer, heap_offset: usize) -> Result<()>;
+    /// How many bytes a pointer takes up in the output, used for placeholders and null pointers
+    const POINTER_SIZE: usize = size_of::<Self::Pointer>();
+    
+    /// Patches a pointer of `byte_size` bytes at the writer's position to point to `heap_offset`
+    fn apply_reference(&mut self, writer: &mut impl Writer, heap_offset: usize, byte_size: usize) -> Result<()>;
+    
+    /// The size of the whole output gets padded to a multiple of this
+    fn file_alignment(&self) -> usize {
+        1
+    }
     
     // TODO: writing with args
     // TODO: boxed serializing
@@ -210,6 +1162,11 @@ pub trait WriteDomainExt: WriteDomain {
     fn new_ctx() -> WriteCtxImpl<Self::Cat> {
         WriteCtxImpl::new()
     }
+    
+    /// Writes a placeholder of `POINTER_SIZE` bytes that gets patched to point to `token`
+    fn write_pointer_token(&self, ctx: &mut impl WriteCtx<Self::Cat>, token: HeapToken) -> Result<()> {
+        ctx.write_token_with(Self::POINTER_SIZE, token)
+    }
 }
 
 impl<T: WriteDomain> WriteDomainExt for T {}
@@ -221,6 +1178,27 @@ pub trait CanWriteBox<C: HeapCategory>: WriteDomain<Cat = C> {
         ctx: &mut W,
         write_content: impl FnOnce(&mut Self, &mut W::InnerCtx<'_>) -> Result<()>,
     ) -> Result<()>;
+    
+    /// Writes a null pointer, which is `POINTER_SIZE` zero bytes unless overridden
+    fn write_box_null(&mut self, ctx: &mut impl WriteCtx<C>) -> Result<()> {
+        write_padding(ctx.cur_writer(), Self::POINTER_SIZE)
+    }
+    
+    /// Writes a pointer to a new block holding all `values`, unlike `write_slice_of` which writes them inline.
+    /// The length isn't written, so it has to be stored somewhere else
+    fn write_boxed_slice_of<T, W: WriteCtx<C>>(
+        &mut self,
+        ctx: &mut W,
+        values: &[T],
+        write_content: impl Fn(&mut Self, &mut W::InnerCtx<'_>, &T) -> Result<()>,
+    ) -> Result<()> {
+        self.write_box_of(ctx, |domain, ctx| {
+            for value in values {
+                write_content(domain, ctx, value)?;
+            }
+            Ok(())
+        })
+    }
 }
 
 pub trait WriteBoxFallbackExt<C: HeapCategory>: CanWriteBox<C> {
@@ -229,6 +1207,50 @@ pub trait WriteBoxFallbackExt<C: HeapCategory>: CanWriteBox<C> {
             value.to_writer(ctx, domain)
         })
     }
+    
+    fn write_box_nullable_fallback<T: Writable<C, Self> + 'static>(&mut self, ctx: &mut impl WriteCtx<C>, value: Option<&T>) -> Result<()> {
+        match value {
+            Some(value) => self.write_box_fallback(ctx, value),
+            None => self.write_box_null(ctx),
+        }
+    }
+    
+    fn write_boxed_slice_fallback<T: Writable<C, Self> + 'static>(&mut self, ctx: &mut impl WriteCtx<C>, values: &[T]) -> Result<()> {
+        self.write_boxed_slice_of(ctx, values, |domain, ctx, value| {
+            value.to_writer(ctx, domain)
+        })
+    }
+    
+    /// Writes a u32 element count followed by a pointer to the elements, see `ReadDomainExt::read_count_ptr_array`.
+    /// Empty arrays get a null pointer
+    fn write_count_ptr_array<T: Writable<C, Self> + 'static>(&mut self, ctx: &mut impl WriteCtx<C>, values: &[T]) -> Result<()> {
+        let count = u32::try_from(values.len())
+            .map_err(|_| anyhow!("Cannot write array of {} elements, the count has to fit into a u32", values.len()))?;
+        count.to_writer(ctx, self)?;
+        
+        if values.is_empty() {
+            self.write_box_null(ctx)
+        } else {
+            self.write_boxed_slice_fallback(ctx, values)
+        }
+    }
+    
+    fn write_box_token_fallback<T: Writable<C, Self> + 'static>(&mut self, ctx: &mut impl WriteCtx<C>, value: &T) -> Result<TypedToken<T>> {
+        let token = ctx.allocate_next_block(None, |ctx| {
+            value.to_writer(ctx, self)
+        })?;
+        
+        Ok(TypedToken::new(token))
+    }
+    
+    /// Writes a pointer to a null terminated string in a new heap block.
+    /// Like every boxed value, the string ends up after all blocks allocated before it in the same category,
+    /// so strings and the structs pointing to them are interleaved unless the strings use their own category.
+    fn write_str_boxed(&mut self, ctx: &mut impl WriteCtx<C>, string: &str) -> Result<()> {
+        self.write_box_of(ctx, |_, ctx| {
+            ctx.write_c_str(string)
+        })
+    }
 }
 
 impl<C: HeapCategory, D: CanWriteBox<C>> WriteBoxFallbackExt<C> for D {}
@@ -242,10 +1264,61 @@ pub trait WriteBoxExt<C: HeapCategory>: CanWriteBox<C> {
             domain.write(ctx, value)
         })
     }
+    
+    /// Writes `value` into a new block without pointing to it yet, so that several pointers can share it
+    fn write_box_token<T: 'static>(&mut self, ctx: &mut impl WriteCtx<C>, value: &T) -> Result<TypedToken<T>>
+    where
+        Self: CanWrite<C, T>,
+    {
+        let token = ctx.allocate_next_block(None, |ctx| {
+            self.write(ctx, value)
+        })?;
+        
+        Ok(TypedToken::new(token))
+    }
 }
 
 impl<C: HeapCategory, D: CanWriteBox<C>> WriteBoxExt<C> for D {}
 
+// type parameter not needed with next solver
+pub trait CanWriteMap<C: HeapCategory>: WriteDomain<Cat = C> {
+    /// `entries` are sorted by key to keep the output reproducible
+    fn write_sorted_entries_of<K: 'static, V: 'static, W: WriteCtx<C>>(
+        &mut self,
+        ctx: &mut W,
+        entries: &[(&K, &V)],
+        write_entry: impl Fn(&mut Self, &mut W, &K, &V) -> Result<()>,
+    ) -> Result<()>;
+}
+
+pub trait WriteMapExt<C: HeapCategory>: CanWriteMap<C> {
+    /// Writes all entries ordered by key, regardless of the map's iteration order
+    fn write_map_of<K: Ord + 'static, V: 'static, W: WriteCtx<C>>(
+        &mut self,
+        ctx: &mut W,
+        map: &HashMap<K, V>,
+        write_entry: impl Fn(&mut Self, &mut W, &K, &V) -> Result<()>,
+    ) -> Result<()> {
+        let mut entries: Vec<(&K, &V)> = map.iter().collect();
+        entries.sort_unstable_by_key(|(key, _)| *key);
+        
+        self.write_sorted_entries_of(ctx, &entries, write_entry)
+    }
+}
+
+impl<C: HeapCategory, D: CanWriteMap<C>> WriteMapExt<C> for D {}
+
+pub trait WriteMapFallbackExt<C: HeapCategory>: CanWriteMap<C> {
+    fn write_map_fallback<K: Writable<C, Self> + Ord + 'static, V: Writable<C, Self> + 'static>(&mut self, ctx: &mut impl WriteCtx<C>, map: &HashMap<K, V>) -> Result<()> {
+        self.write_map_of(ctx, map, |domain, ctx, key, value| {
+            key.to_writer(ctx, domain)?;
+            value.to_writer(ctx, domain)
+        })
+    }
+}
+
+impl<C: HeapCategory, D: CanWriteMap<C>> WriteMapFallbackExt<C> for D {}
+
 // type parameter not needed with next solver
 pub trait CanWriteSlice<C: HeapCategory>: WriteDomain<Cat = C> {
     fn write_slice_of<T: 'static, W: WriteCtx<C>>(
@@ -254,6 +1327,30 @@ pub trait CanWriteSlice<C: HeapCategory>: WriteDomain<Cat = C> {
         values: &[T],
         write_content: impl Fn(&mut Self, &mut W::InnerCtx<'_>, &T) -> Result<()>,
     ) -> Result<()>;
+    
+    /// Like `write_slice_of`, but aligns every element to `element_alignment`, including the first one,
+    /// so the array itself starts aligned as well. If `pad_end` is set, the end of the last element is padded too.
+    fn write_slice_aligned_of<T: 'static, W: WriteCtx<C>>(
+        &mut self,
+        ctx: &mut W,
+        values: &[T],
+        element_alignment: usize,
+        pad_end: bool,
+        write_content: impl Fn(&mut Self, &mut W::InnerCtx<'_>, &T) -> Result<()>,
+    ) -> Result<()> {
+        let written_count = Cell::new(0);
+        
+        self.write_slice_of(ctx, values, |domain, ctx, value| {
+            ctx.align_current_block(element_alignment)?;
+            write_content(domain, ctx, value)?;
+            
+            written_count.set(written_count.get() + 1);
+            if pad_end && written_count.get() == values.len() {
+                ctx.align_to(element_alignment)?;
+            }
+            Ok(())
+        })
+    }
 }
 
 pub trait WriteSliceFallbackExt<C: HeapCategory>: CanWriteSlice<C> {
@@ -262,6 +1359,12 @@ pub trait WriteSliceFallbackExt<C: HeapCategory>: CanWriteSlice<C> {
             value.to_writer(ctx, domain)
         })
     }
+    
+    fn write_slice_aligned_fallback<T: Writable<C, Self> + 'static>(&mut self, ctx: &mut impl WriteCtx<C>, values: &[T], element_alignment: usize, pad_end: bool) -> Result<()> {
+        self.write_slice_aligned_of(ctx, values, element_alignment, pad_end, |domain, ctx, value| {
+            value.to_writer(ctx, domain)
+        })
+    }
 }
 
 impl<C: HeapCategory, D: CanWriteSlice<C>> WriteSliceFallbackExt<C> for D {}
@@ -362,6 +1465,19 @@ pub trait Writable<C: HeapCategory, D: WriteDomain<Cat = C>>: Sized {
     fn to_writer_post(&self, ctx: &mut impl WriteCtx<C>, domain: &mut D) -> Result<()> {
         self.to_writer_unboxed_post(ctx, domain)
     }
+    
+    /// Writes several values in a row, can be overridden to write them all at once
+    fn to_writer_slice(values: &[Self], ctx: &mut impl WriteCtx<C>, domain: &mut D) -> Result<()> {
+        for value in values {
+            value.to_writer(ctx, domain)?;
+        }
+        Ok(())
+    }
+}
+
+/// Write-side counterpart to `ReadableWithArgs`, for types whose layout depends on arguments
+pub trait WritableWithArgs<C: HeapCategory, D: WriteDomain<Cat = C>, A>: Sized {
+    fn to_writer_args(&self, ctx: &mut impl WriteCtx<C>, domain: &mut D, args: A) -> Result<()>;
 }
 
 pub trait SimpleWritable<D: WriteDomain>: Sized {
@@ -370,6 +1486,13 @@ pub trait SimpleWritable<D: WriteDomain>: Sized {
 
 #[macro_export]
 macro_rules! impl_writable_from_simple {
+    (impl [$($generic:tt)*] $type:ty) => {
+        impl<$($generic)*, C: $crate::HeapCategory, D: $crate::WriteDomain<Cat = C>> $crate::Writable<C, D> for $type {
+            fn to_writer_unboxed(&self, ctx: &mut impl $crate::WriteCtx<C>, domain: &mut D) -> Result<()> {
+                self.to_writer_simple(ctx.cur_writer(), domain)
+            }
+        }
+    };
     ($type:ty) => {
         impl<C: $crate::HeapCategory, D: $crate::WriteDomain<Cat = C>> $crate::Writable<C, D> for $type {
             fn to_writer_unboxed(&self, ctx: &mut impl $crate::WriteCtx<C>, domain: &mut D) -> Result<()> {
@@ -379,33 +1502,129 @@ macro_rules! impl_writable_from_simple {
     };
 }
 
+/// Implements `CanWrite` for several types at once:
+/// `impl_can_write!(impl [C: HeapCategory] Domain<C>, C { Type => (domain, ctx, value) expr, ... })`
+#[macro_export]
+macro_rules! impl_can_write {
+    (@single [$($generic:tt)*], $domain_type:ty, $cat:ty, $type:ty, $domain:ident, $ctx:ident, $value:ident, $body:expr) => {
+        impl<$($generic)*> $crate::CanWrite<$cat, $type> for $domain_type {
+            fn write(&mut self, ctx: &mut impl $crate::WriteCtx<$cat>, value: &$type) -> ::anyhow::Result<()> {
+                let $domain = self;
+                let $ctx = ctx;
+                let $value = value;
+                $body
+            }
+        }
+    };
+    (impl $generics:tt $domain_type:ty, $cat:ty { $($type:ty => ($domain:ident, $ctx:ident, $value:ident) $body:expr),* $(,)? }) => {
+        $(
+            $crate::impl_can_write!(@single $generics, $domain_type, $cat, $type, $domain, $ctx, $value, $body);
+        )*
+    };
+    ($domain_type:ty, $cat:ty { $($type:ty => ($domain:ident, $ctx:ident, $value:ident) $body:expr),* $(,)? }) => {
+        $(
+            $crate::impl_can_write!(@single [], $domain_type, $cat, $type, $domain, $ctx, $value, $body);
+        )*
+    };
+}
+
+/// Implements `Readable` and `Writable` for an enum stored as a tag followed by the variant's field, if it has one:
+/// `impl_readable_enum!(MyEnum: u16 { 0 => A, 1 => B(u32), 2 => C })`
+#[macro_export]
+macro_rules! impl_readable_enum {
+    (@read $reader:ident, $domain:ident, $enum:ident::$variant:ident) => {
+        $enum::$variant
+    };
+    (@read $reader:ident, $domain:ident, $enum:ident::$variant:ident($field:ty)) => {
+        $enum::$variant(<$field as $crate::Readable<_>>::from_reader($reader, $domain)?)
+    };
+    (@pattern $value:ident, $enum:ident::$variant:ident) => {
+        $enum::$variant
+    };
+    (@pattern $value:ident, $enum:ident::$variant:ident($field:ty)) => {
+        $enum::$variant($value)
+    };
+    (@write $value:ident, $ctx:ident, $domain:ident) => {
+        ::core::result::Result::Ok(())
+    };
+    (@write $value:ident, $ctx:ident, $domain:ident, $field:ty) => {
+        <$field as $crate::Writable<_, _>>::to_writer($value, $ctx, $domain)
+    };
+    ($enum:ident: $tag_type:ty { $($tag:literal => $variant:ident $(($field:ty))?),* $(,)? }) => {
+        impl<D: $crate::ReadDomain> $crate::Readable<D> for $enum
+        where
+            $($($field: $crate::Readable<D>,)?)*
+        {
+            fn from_reader_unboxed<R: $crate::Reader>(reader: &mut R, domain: D) -> ::anyhow::Result<Self> {
+                let offset = $crate::Reader::position(reader)?;
+                let tag: $tag_type = $crate::Readable::from_reader(reader, domain)?;
+                
+                match tag {
+                    $($tag => ::core::result::Result::Ok($crate::impl_readable_enum!(@read reader, domain, $enum::$variant $(($field))?)),)*
+                    _ => ::core::result::Result::Err(::anyhow::anyhow!(
+                        "Unknown tag {tag} for enum {} (at offset 0x{offset:x})", stringify!($enum),
+                    )),
+                }
+            }
+        }
+        
+        impl<C: $crate::HeapCategory, D: $crate::WriteDomain<Cat = C>> $crate::Writable<C, D> for $enum
+        where
+            $($($field: $crate::Writable<C, D>,)?)*
+        {
+            fn to_writer_unboxed(&self, ctx: &mut impl $crate::WriteCtx<C>, domain: &mut D) -> ::anyhow::Result<()> {
+                // exhaustive, so that variants missing from the macro invocation fail to compile
+                match self {
+                    $($crate::impl_readable_enum!(@pattern value, $enum::$variant $(($field))?) => {
+                        let tag: $tag_type = $tag;
+                        $crate::Writable::to_writer(&tag, ctx, domain)?;
+                        $crate::impl_readable_enum!(@write value, ctx, domain $(, $field)?)
+                    },)*
+                }
+            }
+        }
+    };
+}
+
 // boxed serialization stuff
 // TODO: merge WriteCtxImpl and InnerWriteCtx into one struct and remove this trait
 pub trait WriteCtx<Cat>: Deref<Target = WriteHeap<Self::Writer>> + DerefMut
 where
     Cat: Eq + Hash + Default + Clone,
 {
-    type Writer: Writer;
-    type InnerCtx<'a>: WriteCtx<Cat, Writer = Self::Writer>
-    where
-        Self: 'a;
+    type Writer: Writer + Default;
+    /// The context passed to the callbacks of the `allocate_next_block` methods. Those take it for any lifetime (`'_`)
+    /// instead of the lifetime of `&mut self`, because nested contexts borrow the outermost context only temporarily.
+    /// That also means there's no `where Self: 'a` bound, as it can't hold for every lifetime
+    type InnerCtx<'a>: WriteCtx<Cat, Writer = Self::Writer>;
     
-    fn allocate_next_block<'a>(
-        &'a mut self,
+    fn allocate_next_block(
+        &mut self,
         category: Option<Cat>,
-        content_callback: impl FnOnce(&mut Self::InnerCtx<'a>) -> Result<()>,
-    ) -> Result<HeapToken>
-    where
-        Cat: 'a;
+        content_callback: impl FnOnce(&mut Self::InnerCtx<'_>) -> Result<()>,
+    ) -> Result<HeapToken>;
     
-    fn allocate_next_block_aligned<'a>(
-        &'a mut self,
+    fn allocate_next_block_aligned(
+        &mut self,
         category: Option<Cat>,
         alignment: usize,
-        content_callback: impl FnOnce(&mut Self::InnerCtx<'a>) -> Result<()>
-    ) -> Result<HeapToken>
-    where
-        Cat: 'a;
+        content_callback: impl FnOnce(&mut Self::InnerCtx<'_>) -> Result<()>,
+    ) -> Result<HeapToken>;
+    
+    /// Like `allocate_next_block`, but returns the token of an identical previously written block if there is one.
+    /// Only use this for position-independent content, i.e. content without pointers or offsets relative to itself.
+    fn allocate_next_block_dedup(
+        &mut self,
+        category: Option<Cat>,
+        content_callback: impl FnOnce(&mut Self::InnerCtx<'_>) -> Result<()>,
+    ) -> Result<HeapToken>;
+    
+    /// Blocks written by `allocate_next_block_dedup` so far, for nested contexts to share those of the outermost context.
+    /// Contexts without one don't deduplicate blocks written through their nested contexts
+    #[doc(hidden)]
+    fn dedup_pool(&mut self) -> Option<&mut DedupPool> {
+        None
+    }
     
     fn heap(&self, category: &Cat) -> Option<&WriteHeap<Self::Writer>>;
     fn heap_mut(&mut self, category: Cat) -> &mut WriteHeap<Self::Writer>;
@@ -413,6 +1632,18 @@ where
     fn heap_id_of(&mut self, category: Cat) -> HeapID;
     fn heap_token_at_current_pos(&mut self) -> Result<HeapToken>;
     
+    /// Where the current position ends up in the final output, once resolved
+    fn current_heap_token(&mut self) -> Result<HeapToken> {
+        self.heap_token_at_current_pos()
+    }
+    
+    /// Writes a `BYTE_SIZE` byte pointer that points to itself, for structs that store their own offset
+    fn reserve_self_pointer<const BYTE_SIZE: usize>(&mut self) -> Result<HeapToken> {
+        let token = self.heap_token_at_current_pos()?;
+        self.write_token::<BYTE_SIZE>(token)?;
+        Ok(token)
+    }
+    
     // useful for child ctx's
     fn set_heap(&mut self, category: Cat, heap: WriteHeap<Self::Writer>);
     fn remove_heap(&mut self, category: &Cat) -> WriteHeap<Self::Writer>;
@@ -420,17 +1651,108 @@ where
 
 pub type WriteCtxWriter = Cursor<Vec<u8>>;
 
+/// Previously written block contents and where they were written to
+pub type DedupPool = HashMap<(HeapID, Vec<u8>), HeapToken>;
+
 pub struct WriteCtxImpl<C: HeapCategory> {
     default_heap: WriteHeap<WriteCtxWriter>,
-    heaps: IndexMap<C, Option<WriteHeap<WriteCtxWriter>>>,
+    heaps: IndexMap<C, Option<WriteHeap<WriteCtxWriter>>, BuildHasher>,
+    dedup_pool: DedupPool,
+    category_order: Vec<C>,
 }
 
 impl<C: HeapCategory> WriteCtxImpl<C> {
     pub fn new() -> Self {
         WriteCtxImpl {
             default_heap: WriteHeap::new(),
-            heaps: IndexMap::new(),
+            heaps: IndexMap::default(),
+            dedup_pool: DedupPool::default(),
+            category_order: Vec::new(),
+        }
+    }
+    
+    /// Sets the order the heaps are written out in by `to_buffer`.
+    /// Categories missing from `order` come after the listed ones, sorted by `Ord`.
+    pub fn with_category_order(mut self, order: Vec<C>) -> Self {
+        self.category_order = order;
+        self
+    }
+}
+
+impl<C: HeapCategory> WriteCtxImpl<C> {
+    /// Flattens all heaps into one buffer, ordered by category, and applies all relocations.
+    /// If `block_offsets` is given, it receives the absolute offset of every block in output order.
+    pub fn to_buffer(self, domain: &mut impl WriteDomain, block_offsets: Option<&mut Vec<usize>>) -> Result<Vec<u8>> {
+        let mut output = WriteCtxWriter::default();
+        let (resolver, _) = self.write_resolved(domain, &mut output)?;
+        
+        if let Some(block_offsets) = block_offsets {
+            *block_offsets = resolver.block_offsets;
+        }
+        
+        Ok(output.into_inner())
+    }
+    
+    /// Like `to_buffer`, but also returns every patched pointer, e.g. for writing relocation tables
+    pub fn to_buffer_with_relocations(self, domain: &mut impl WriteDomain) -> Result<(Vec<u8>, Vec<ResolvedRelocation>)> {
+        let mut output = WriteCtxWriter::default();
+        let (_, relocations) = self.write_resolved(domain, &mut output)?;
+        Ok((output.into_inner(), relocations))
+    }
+    
+    /// Like `to_buffer`, but appends a checksum computed over the entire output by `checksum`,
+    /// e.g. `|bytes| checksums::crc32(bytes).to_le_bytes().to_vec()`
+    pub fn to_buffer_checked(self, domain: &mut impl WriteDomain, checksum: impl Fn(&[u8]) -> Vec<u8>) -> Result<Vec<u8>> {
+        let mut buffer = self.to_buffer(domain, None)?;
+        let checksum = checksum(&buffer);
+        buffer.extend_from_slice(&checksum);
+        Ok(buffer)
+    }
+    
+    /// Writes all heaps directly into `out` instead of an intermediate buffer, starting at its current position,
+    /// e.g. into a `BufWriter<File>`. Pointers are absolute offsets into `out`.
+    pub fn to_writer<W: Write + Seek>(self, domain: &mut impl WriteDomain, out: &mut W) -> Result<()> {
+        self.write_resolved(domain, out)?;
+        Ok(())
+    }
+    
+    fn write_resolved<'a, W: Write + Seek>(self, domain: &mut impl WriteDomain, out: &'a mut W) -> Result<(HeapResolver<&'a mut W>, Vec<ResolvedRelocation>)> {
+        let mut resolver = HeapResolver::with_output(out);
+        let relocations = self.resolve_into(&mut resolver, domain)?;
+        Ok((resolver, relocations))
+    }
+    
+    fn resolve_into<W: Writer>(mut self, resolver: &mut HeapResolver<W>, domain: &mut impl WriteDomain) -> Result<Vec<ResolvedRelocation>> {
+        let default_heap_id = self.heap_id_of(C::default());
+        let mut heaps = vec![(C::default(), default_heap_id, mem::take(&mut self.default_heap))];
+        
+        for (index, (category, heap)) in self.heaps.into_iter().enumerate() {
+            if let Some(heap) = heap {
+                heaps.push((category, HeapID(index as u32), heap));
+            }
+        }
+        
+        let order = &self.category_order;
+        heaps.sort_by(|(a, _, _), (b, _, _)| {
+            let index_a = order.iter().position(|category| category == a).unwrap_or(order.len());
+            let index_b = order.iter().position(|category| category == b).unwrap_or(order.len());
+            index_a.cmp(&index_b).then_with(|| a.cmp(b))
+        });
+        
+        for (_, heap_id, heap) in &heaps {
+            resolver.write_heap(*heap_id, heap)?;
         }
+        
+        let relocations = resolver.apply_relocations(domain)?;
+        
+        resolver.output.seek(SeekFrom::End(0))?;
+        align_to(&mut resolver.output, domain.file_alignment())?;
+        
+        for (_, heap_id, heap) in &mut heaps {
+            resolver.apply_deferred(*heap_id, heap)?;
+        }
+        
+        Ok(relocations)
     }
 }
 
@@ -442,16 +1764,13 @@ impl<C: HeapCategory> Default for WriteCtxImpl<C> {
 
 impl<Cat: HeapCategory> WriteCtx<Cat> for WriteCtxImpl<Cat> {
     type Writer = WriteCtxWriter;
-    type InnerCtx<'a> = InnerWriteCtx<'a, Cat, WriteCtxImpl<Cat>> where Self: 'a;
+    type InnerCtx<'a> = InnerWriteCtx<'a, Cat, WriteCtxImpl<Cat>>;
 
-    fn allocate_next_block<'a>(
-        &'a mut self,
+    fn allocate_next_block(
+        &mut self,
         category: Option<Cat>,
-        content_callback: impl FnOnce(&mut Self::InnerCtx<'a>) -> Result<()>,
-    ) -> Result<HeapToken>
-    where
-        Cat: 'a,
-    {
+        content_callback: impl FnOnce(&mut Self::InnerCtx<'_>) -> Result<()>,
+    ) -> Result<HeapToken> {
         let heap_id = self.heap_id_of(category.clone().unwrap_or_default());
         
         let mut ctx: InnerWriteCtx<'_, Cat, WriteCtxImpl<Cat>> = InnerWriteCtx::new(self, category.unwrap_or_default());
@@ -465,15 +1784,12 @@ impl<Cat: HeapCategory> WriteCtx<Cat> for WriteCtxImpl<Cat> {
         Ok(new_block_token)
     }
     
-    fn allocate_next_block_aligned<'a>(
-        &'a mut self,
+    fn allocate_next_block_aligned(
+        &mut self,
         category: Option<Cat>,
         alignment: usize,
-        content_callback: impl FnOnce(&mut Self::InnerCtx<'a>) -> Result<()>,
-    ) -> Result<HeapToken>
-    where
-        Cat: 'a,
-    {
+        content_callback: impl FnOnce(&mut Self::InnerCtx<'_>) -> Result<()>,
+    ) -> Result<HeapToken> {
         let heap_id = self.heap_id_of(category.clone().unwrap_or_default());
         
         let mut ctx: InnerWriteCtx<'_, Cat, WriteCtxImpl<Cat>> = InnerWriteCtx::new(self, category.unwrap_or_default());
@@ -486,6 +1802,27 @@ impl<Cat: HeapCategory> WriteCtx<Cat> for WriteCtxImpl<Cat> {
         Ok(new_block_token)
     }
     
+    fn allocate_next_block_dedup(
+        &mut self,
+        category: Option<Cat>,
+        content_callback: impl FnOnce(&mut Self::InnerCtx<'_>) -> Result<()>,
+    ) -> Result<HeapToken> {
+        let heap_id = self.heap_id_of(category.clone().unwrap_or_default());
+        
+        let mut ctx: InnerWriteCtx<'_, Cat, WriteCtxImpl<Cat>> = InnerWriteCtx::new(self, category.unwrap_or_default());
+        let prev_current_block = ctx.default_heap.current_block;
+        let new_block_token = ctx.default_heap.seek_to_new_block(0, heap_id)?;
+        
+        content_callback(&mut ctx)?;
+        
+        ctx.default_heap.current_block = prev_current_block;
+        ctx.default_heap.dedup_block(new_block_token, &mut ctx.ctx.dedup_pool)
+    }
+    
+    fn dedup_pool(&mut self) -> Option<&mut DedupPool> {
+        Some(&mut self.dedup_pool)
+    }
+    
     fn heap(&self, category: &Cat) -> Option<&WriteHeap<Self::Writer>> {
         if *category == Cat::default() {
             Some(&self.default_heap)
@@ -556,6 +1893,12 @@ impl<C: HeapCategory> DerefMut for WriteCtxImpl<C> {
     }
 }
 
+/// Context for writing into a newly allocated block. It takes the heap of its category out of
+/// the parent context for as long as it lives and puts it back on drop.
+///
+/// At most one heap is checked out at a time: allocating a nested block first hands the current
+/// heap back to the parent, so a nested context of the same category takes over the same heap
+/// instead of an empty copy of it.
 pub struct InnerWriteCtx<'a, Cat, W>
 where
     Cat: HeapCategory,
@@ -563,6 +1906,9 @@ where
 {
     default_category: Cat,
     default_heap: WriteHeap<WriteCtxWriter>,
+    // false while the heap is handed back to the parent, so that unwinding
+    // out of a nested context doesn't overwrite it with an empty heap
+    owns_heap: bool,
     ctx: &'a mut W,
 }
 
@@ -577,54 +1923,102 @@ where
         Self {
             default_category,
             default_heap,
+            owns_heap: true,
             ctx,
         }
     }
+    
+    /// Temporarily hands the default heap back to the parent context
+    fn with_heap_returned<T>(&mut self, callback: impl FnOnce(&mut W) -> Result<T>) -> Result<T> {
+        self.ctx.set_heap(self.default_category.clone(), mem::take(&mut self.default_heap));
+        self.owns_heap = false;
+        
+        let result = callback(self.ctx);
+        
+        self.default_heap = self.ctx.remove_heap(&self.default_category);
+        self.owns_heap = true;
+        result
+    }
 }
 
 impl<Cat, W> WriteCtx<Cat> for InnerWriteCtx<'_, Cat, W>
 where
     Cat: HeapCategory,
-    W: WriteCtx<Cat, Writer = WriteCtxWriter>,
+    // required by InnerCtx for any lifetime, which is fine as W is always the outermost context
+    W: WriteCtx<Cat, Writer = WriteCtxWriter> + 'static,
 {
     type Writer = WriteCtxWriter;
-    type InnerCtx<'a> = InnerWriteCtx<'a, Cat, Self> where Self: 'a;
+    // nested contexts borrow the outermost context directly so that recursive types
+    // don't create infinitely nested context types
+    type InnerCtx<'a> = InnerWriteCtx<'a, Cat, W>;
 
-    fn allocate_next_block<'a>(
-        &'a mut self,
+    fn allocate_next_block(
+        &mut self,
         category: Option<Cat>,
-        content_callback: impl FnOnce(&mut Self::InnerCtx<'a>) -> Result<()>,
-    ) -> Result<HeapToken> where Cat: 'a {
-        let heap_id = self.ctx.heap_id_of(category.clone().unwrap_or_default());
-        
-        let mut ctx: InnerWriteCtx<'_, Cat, Self> = InnerWriteCtx::new(self, category.unwrap_or_default());
-        
-        let prev_current_block = ctx.default_heap.current_block;
-        let new_block_token = ctx.default_heap.seek_to_new_block(0, heap_id)?;
-        
-        content_callback(&mut ctx)?;
-        
-        ctx.default_heap.current_block = prev_current_block;
-        Ok(new_block_token)
+        content_callback: impl FnOnce(&mut Self::InnerCtx<'_>) -> Result<()>,
+    ) -> Result<HeapToken> {
+        self.with_heap_returned(|parent| {
+            let heap_id = parent.heap_id_of(category.clone().unwrap_or_default());
+            
+            let mut ctx: InnerWriteCtx<'_, Cat, W> = InnerWriteCtx::new(parent, category.unwrap_or_default());
+            
+            let prev_current_block = ctx.default_heap.current_block;
+            let new_block_token = ctx.default_heap.seek_to_new_block(0, heap_id)?;
+            
+            content_callback(&mut ctx)?;
+            
+            ctx.default_heap.current_block = prev_current_block;
+            Ok(new_block_token)
+        })
     }
     
-    fn allocate_next_block_aligned<'a>(
-        &'a mut self,
+    fn allocate_next_block_aligned(
+        &mut self,
         category: Option<Cat>,
         alignment: usize,
-        content_callback: impl FnOnce(&mut Self::InnerCtx<'a>) -> Result<()>,
+        content_callback: impl FnOnce(&mut Self::InnerCtx<'_>) -> Result<()>,
     ) -> Result<HeapToken> {
-        let heap_id = self.ctx.heap_id_of(category.clone().unwrap_or_default());
-        
-        let mut ctx: InnerWriteCtx<'_, Cat, Self> = InnerWriteCtx::new(self, category.unwrap_or_default());
-        
-        let prev_current_block = ctx.default_heap.current_block;
-        let new_block_token = ctx.default_heap.seek_to_new_block(alignment, heap_id)?;
-        
-        content_callback(&mut ctx)?;
-        
-        ctx.default_heap.current_block = prev_current_block;
-        Ok(new_block_token)
+        self.with_heap_returned(|parent| {
+            let heap_id = parent.heap_id_of(category.clone().unwrap_or_default());
+            
+            let mut ctx: InnerWriteCtx<'_, Cat, W> = InnerWriteCtx::new(parent, category.unwrap_or_default());
+            
+            let prev_current_block = ctx.default_heap.current_block;
+            let new_block_token = ctx.default_heap.seek_to_new_block(alignment, heap_id)?;
+            
+            content_callback(&mut ctx)?;
+            
+            ctx.default_heap.current_block = prev_current_block;
+            Ok(new_block_token)
+        })
+    }
+    
+    fn allocate_next_block_dedup(
+        &mut self,
+        category: Option<Cat>,
+        content_callback: impl FnOnce(&mut Self::InnerCtx<'_>) -> Result<()>,
+    ) -> Result<HeapToken> {
+        self.with_heap_returned(|parent| {
+            let heap_id = parent.heap_id_of(category.clone().unwrap_or_default());
+            
+            let mut ctx: InnerWriteCtx<'_, Cat, W> = InnerWriteCtx::new(parent, category.unwrap_or_default());
+            
+            let prev_current_block = ctx.default_heap.current_block;
+            let new_block_token = ctx.default_heap.seek_to_new_block(0, heap_id)?;
+            
+            content_callback(&mut ctx)?;
+            
+            ctx.default_heap.current_block = prev_current_block;
+            
+            match ctx.ctx.dedup_pool() {
+                Some(pool) => ctx.default_heap.dedup_block(new_block_token, pool),
+                None => Ok(new_block_token),
+            }
+        })
+    }
+    
+    fn dedup_pool(&mut self) -> Option<&mut DedupPool> {
+        self.ctx.dedup_pool()
     }
     
     fn heap(&self, category: &Cat) -> Option<&WriteHeap<Self::Writer>> {
@@ -697,13 +2091,34 @@ where
     W: WriteCtx<Cat, Writer = WriteCtxWriter>,
 {
     fn drop(&mut self) {
+        if !self.owns_heap {
+            return;
+        }
+        
         let default_category = mem::take(&mut self.default_category);
         let default_heap = mem::take(&mut self.default_heap);
         self.ctx.set_heap(default_category, default_heap);
     }
 }
 
-pub fn align_to(writer: &mut impl Writer, alignment: usize) -> Result<()> {
+/// Writes `count` zero bytes, in chunks so that no buffer of that size has to be allocated
+pub fn write_zeros(writer: &mut (impl Writer + ?Sized), count: usize) -> Result<()> {
+    let mut remaining = count;
+    
+    while remaining > 0 {
+        let chunk_size = remaining.min(ZEROES.len());
+        writer.write_all(&ZEROES[..chunk_size])?;
+        remaining -= chunk_size;
+    }
+    
+    Ok(())
+}
+
+pub fn write_padding(writer: &mut (impl Writer + ?Sized), size: usize) -> Result<()> {
+    write_zeros(writer, size)
+}
+
+pub fn align_to(writer: &mut (impl Writer + ?Sized), alignment: usize) -> Result<()> {
     if alignment == 0 {
         return Ok(());
     }
@@ -714,8 +2129,7 @@ pub fn align_to(writer: &mut impl Writer, alignment: usize) -> Result<()> {
     // bonkers alignment calculation
     let padding_size = ((alignment - pos) % alignment + alignment) % alignment;
     
-    writer.write_all(&ZEROES[..padding_size as usize])?;
-    Ok(())
+    write_zeros(writer, padding_size as usize)
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
@@ -726,8 +2140,64 @@ pub struct HeapToken {
 }
 
 impl HeapToken {
+    /// Panics if `block_offsets` doesn't contain the token's block, see `try_resolve`
     pub fn resolve(self, block_offsets: &[usize]) -> usize {
-        block_offsets[self.block_id as usize] + self.offset as usize
+        block_offsets[self.block_id as usize] + self.offset
+    }
+    
+    /// Like `resolve`, but fails if the token's block is missing from `block_offsets`,
+    /// e.g. because the token belongs to a different context
+    pub fn try_resolve(self, block_offsets: &[usize]) -> Result<usize> {
+        let block_start = block_offsets.get(self.block_id as usize)
+            .ok_or_else(|| anyhow!("Block {} of {self:?} does not exist, only {} blocks were written", self.block_id, block_offsets.len()))?;
+        
+        block_start.checked_add(self.offset)
+            .ok_or_else(|| anyhow!("Offset of {self:?} overflows"))
+    }
+}
+
+/// A `HeapToken` that remembers the type of the value it points to,
+/// which can be written as a pointer any number of times
+pub struct TypedToken<T> {
+    token: HeapToken,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> TypedToken<T> {
+    pub fn new(token: HeapToken) -> Self {
+        TypedToken { token, _marker: PhantomData }
+    }
+    
+    pub fn token(self) -> HeapToken {
+        self.token
+    }
+    
+    /// Writes a pointer to the value, see `WriteDomainExt::write_pointer_token`
+    pub fn write<D: WriteDomain>(self, ctx: &mut impl WriteCtx<D::Cat>, domain: &D) -> Result<()> {
+        domain.write_pointer_token(ctx, self.token)
+    }
+}
+
+// implemented by hand so that they don't require T to implement them
+impl<T> Clone for TypedToken<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for TypedToken<T> {}
+
+impl<T> PartialEq for TypedToken<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.token == other.token
+    }
+}
+
+impl<T> Eq for TypedToken<T> {}
+
+impl<T> Debug for TypedToken<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("TypedToken").field(&self.token).finish()
     }
 }
 
@@ -750,24 +2220,68 @@ impl Ord for HeapToken {
 #[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
 pub struct HeapID(pub u32);
 
-#[derive(Clone, Debug, Default)]
+#[derive(Debug, Default)]
 pub struct HeapBlock<W: Writer> {
-    relocations: Vec<(usize, HeapToken)>,
+    /// (offset in block, pointer size in bytes, target)
+    relocations: Vec<(usize, usize, HeapToken)>,
+    deferred: Vec<DeferredWrite>,
+    /// Alignment of the block's start in the final output
+    alignment: usize,
     writer: W,
 }
 
-impl<W: Writer> HeapBlock<W> {
+impl<W: Writer + Default> HeapBlock<W> {
     pub fn new() -> Self {
         Self::default()
     }
 }
 
+type DeferredFn = Box<dyn FnOnce(&FinalLayout<'_>) -> Vec<u8>>;
+
+/// Bytes at `offset` in a block that are only computed once the final layout is known
+struct DeferredWrite {
+    offset: usize,
+    compute: DeferredFn,
+}
+
+impl Debug for DeferredWrite {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DeferredWrite")
+            .field("offset", &self.offset)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Where everything ended up in the final output, passed to the callbacks of `WriteHeap::write_deferred`
+#[derive(Clone, Copy, Debug)]
+pub struct FinalLayout<'a> {
+    heap_block_offsets: &'a HashMap<HeapID, Vec<usize>>,
+    total_len: usize,
+}
+
+impl FinalLayout<'_> {
+    /// Absolute offset of `token` in the output
+    pub fn resolve(&self, token: HeapToken) -> Result<usize> {
+        let block_start = self.heap_block_offsets.get(&token.heap_id)
+            .and_then(|offsets| offsets.get(token.block_id as usize))
+            .ok_or_else(|| anyhow!("{token:?} points to a block that was never written"))?;
+        
+        block_start.checked_add(token.offset)
+            .ok_or_else(|| anyhow!("Offset of {token:?} overflows"))
+    }
+    
+    /// Length of the entire output, including the padding for the domain's file alignment
+    pub fn total_len(&self) -> usize {
+        self.total_len
+    }
+}
+
 pub struct WriteHeap<W: Writer> {
     current_block: usize,
     blocks: Vec<HeapBlock<W>>,
 }
 
-impl<W: Writer> WriteHeap<W> {
+impl<W: Writer + Default> WriteHeap<W> {
     pub fn new() -> Self {
         WriteHeap {
             current_block: 0,
@@ -775,46 +2289,165 @@ impl<W: Writer> WriteHeap<W> {
         }
     }
     
+    fn seek_to_new_block(&mut self, alignment: usize, heap_id: HeapID) -> Result<HeapToken> {
+        if self.current_block == self.blocks.len() - 1 {
+            // allocate new block
+            self.current_block = self.blocks.len();
+            self.blocks.push(HeapBlock {
+                alignment,
+                ..HeapBlock::new()
+            });
+        } else {
+            self.current_block += 1;
+            
+            // aligning within the block only holds up if the block itself is aligned
+            let block = &mut self.blocks[self.current_block];
+            block.alignment = block.alignment.max(alignment);
+            self.align_to(alignment)?;
+        }
+        
+        self.heap_token_at_current_pos_inner(heap_id)
+    }
+}
+
+impl<W: Writer> WriteHeap<W> {
     pub fn cur_writer(&mut self) -> &mut W {
         &mut self.blocks[self.current_block].writer
     }
     
+    pub fn current_block(&self) -> usize {
+        self.current_block
+    }
+    
+    /// Switches to another existing block, see `util::HeapGuard` for switching back automatically
+    pub fn set_current_block(&mut self, block_id: usize) -> Result<()> {
+        if block_id >= self.blocks.len() {
+            return Err(anyhow!("Block {block_id} does not exist, heap only has {} blocks", self.blocks.len()));
+        }
+        
+        self.current_block = block_id;
+        Ok(())
+    }
+    
+    /// Writes a placeholder that gets patched to point to `token` once resolved.
+    /// Every call records its own relocation, so the same token can be written at any number of sites
+    /// to let several pointers share one block.
     pub fn write_token<const BYTE_SIZE: usize>(&mut self, token: HeapToken) -> Result<()> {
         let block = &mut self.blocks[self.current_block];
-        block.relocations.push((block.writer.position()? as usize, token));
+        block.relocations.push((block.writer.position()? as usize, BYTE_SIZE, token));
         
         self.cur_writer().write_all(&const { [0; BYTE_SIZE] })?;
         Ok(())
     }
     
+    pub fn write_token32(&mut self, token: HeapToken) -> Result<()> {
+        self.write_token::<4>(token)
+    }
+    
+    pub fn write_token64(&mut self, token: HeapToken) -> Result<()> {
+        self.write_token::<8>(token)
+    }
+    
+    /// Like `write_token`, but with a pointer size only known at runtime, which has to be 2, 4 or 8
+    pub fn write_token_with(&mut self, byte_size: usize, token: HeapToken) -> Result<()> {
+        if ![2, 4, 8].contains(&byte_size) {
+            return Err(anyhow!("Cannot write pointer of {byte_size} bytes, expected 2, 4 or 8"));
+        }
+        
+        let block = &mut self.blocks[self.current_block];
+        block.relocations.push((block.writer.position()? as usize, byte_size, token));
+        
+        self.cur_writer().write_all(&ZEROES[..byte_size])?;
+        Ok(())
+    }
+    
+    /// Writes an `N` byte placeholder that gets filled in by `compute` once the final layout is known,
+    /// for values like the total file size or offsets that aren't pointers
+    pub fn write_deferred<const N: usize>(&mut self, compute: impl FnOnce(&FinalLayout<'_>) -> [u8; N] + 'static) -> Result<()> {
+        let block = &mut self.blocks[self.current_block];
+        block.deferred.push(DeferredWrite {
+            offset: block.writer.position()? as usize,
+            compute: Box::new(move |layout| compute(layout).to_vec()),
+        });
+        
+        self.cur_writer().write_all(&const { [0; N] })?;
+        Ok(())
+    }
+    
     pub fn align_to(&mut self, alignment: usize) -> Result<()> {
         align_to(self.cur_writer(), alignment)
     }
     
+    /// Like `align_to`, but also makes sure the current block starts at a multiple of `alignment`
+    /// in the final output, so that the padding still lines up after relocation.
+    /// This is what `WriteCtx::allocate_next_block_aligned` does for the start of a new block.
+    pub fn align_current_block(&mut self, alignment: usize) -> Result<()> {
+        let block = &mut self.blocks[self.current_block];
+        block.alignment = block.alignment.max(alignment);
+        self.align_to(alignment)
+    }
+    
     fn heap_token_at_current_pos_inner(&mut self, heap_id: HeapID) -> Result<HeapToken> {
+        let position = self.cur_writer().position()?;
+        
         Ok(HeapToken {
             heap_id,
-            block_id: self.current_block as u32,
-            offset: self.cur_writer().position()? as usize,
+            block_id: u32::try_from(self.current_block)
+                .map_err(|_| anyhow!("Heap has too many blocks ({}) to be referenced", self.current_block))?,
+            offset: usize::try_from(position)
+                .map_err(|_| anyhow!("Block offset 0x{position:x} is too large to be referenced"))?,
         })
     }
+}
+
+impl WriteHeap<WriteCtxWriter> {
+    pub fn block_count(&self) -> usize {
+        self.blocks.len()
+    }
     
-    fn seek_to_new_block(&mut self, alignment: usize, heap_id: HeapID) -> Result<HeapToken> {
-        if self.current_block == self.blocks.len() - 1 {
-            // allocate new block
-            self.current_block = self.blocks.len();
-            self.blocks.push(HeapBlock::new());
-            // TODO: add alignment to HeapBlock
-        } else {
-            self.current_block += 1;
-            self.align_to(alignment)?;
+    pub fn block_len(&self, block_id: usize) -> Option<u64> {
+        self.blocks.get(block_id).map(|block| block.writer.get_ref().len() as u64)
+    }
+    
+    /// Size of all blocks combined, not including alignment between them
+    pub fn total_len(&self) -> u64 {
+        self.blocks.iter().map(|block| block.writer.get_ref().len() as u64).sum()
+    }
+    
+    /// Drops the content written since `token` if identical content has already been written before
+    /// and returns the token pointing to that, otherwise adds it to the pool
+    fn dedup_block(&mut self, token: HeapToken, pool: &mut DedupPool) -> Result<HeapToken> {
+        let block = &mut self.blocks[token.block_id as usize];
+        
+        // content containing pointers only looks identical because of the zeroed placeholders
+        if block.relocations.iter().any(|(offset, _, _)| *offset >= token.offset)
+            || block.deferred.iter().any(|deferred| deferred.offset >= token.offset) {
+            return Ok(token);
         }
         
-        self.heap_token_at_current_pos_inner(heap_id)
+        let content = block.writer.get_ref()[token.offset..].to_vec();
+        
+        if let Some(existing) = pool.get(&(token.heap_id, content.clone())) {
+            block.writer.get_mut().truncate(token.offset);
+            block.writer.set_position(token.offset as u64);
+            return Ok(*existing);
+        }
+        
+        pool.insert((token.heap_id, content), token);
+        Ok(token)
+    }
+}
+
+impl<W: Writer> Debug for WriteHeap<W> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WriteHeap")
+            .field("current_block", &self.current_block)
+            .field("block_count", &self.blocks.len())
+            .finish_non_exhaustive()
     }
 }
 
-impl<W: Writer> Default for WriteHeap<W> {
+impl<W: Writer + Default> Default for WriteHeap<W> {
     fn default() -> Self {
         Self::new()
     }
@@ -837,68 +2470,110 @@ impl<W: Writer> DerefMut for WriteHeap<W> {
     }
 }
 
+/// A patched pointer in the final output
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ResolvedRelocation {
+    pub at: usize,
+    pub points_to: usize,
+}
+
 #[derive(Debug, Default)]
-pub struct HeapResolver {
+pub struct HeapResolver<W: Writer = WriteCtxWriter> {
     pub block_offsets: Vec<usize>,
-    pub all_relocations: Vec<(HeapID, usize, HeapToken)>,
-    pub output_buffers: HashMap<HeapID, RefCell<Cursor<Vec<u8>>>>,
+    /// absolute offset of every block, indexed by heap and block id
+    pub heap_block_offsets: HashMap<HeapID, Vec<usize>>,
+    /// (absolute offset, pointer size in bytes, target)
+    pub all_relocations: Vec<(usize, usize, HeapToken)>,
+    pub output: W,
 }
 
 impl HeapResolver {
-    pub fn write_heap(&mut self, domain: &mut impl WriteDomain, heap_id: HeapID, heap: &WriteHeap<WriteCtxWriter>) -> Result<()> {
-        // buffer to avoid reallocating every iteration
-        let mut relocations_from_current = Vec::new();
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<W: Writer> HeapResolver<W> {
+    /// Resolves into `output`, which can also be a borrowed writer like `&mut BufWriter<File>`
+    pub fn with_output(output: W) -> Self {
+        HeapResolver {
+            block_offsets: Vec::new(),
+            heap_block_offsets: HashMap::default(),
+            all_relocations: Vec::new(),
+            output,
+        }
+    }
+    
+    /// Writes out all blocks of a heap and collects their relocations, which are applied later in `apply_relocations`
+    pub fn write_heap(&mut self, heap_id: HeapID, heap: &WriteHeap<WriteCtxWriter>) -> Result<()> {
+        let heap_block_offsets = self.heap_block_offsets.entry(heap_id).or_default();
         
-        for (block_id, block) in heap.blocks.iter().enumerate() {
-            self.output_buffers.entry(heap_id).or_default();
-            let writer = &mut *self.output_buffers[&heap_id].borrow_mut();
+        for block in &heap.blocks {
+            let writer = &mut self.output;
             
-            let block_start = Cursor::position(writer) as usize;
+            align_to(writer, block.alignment)?;
+            let block_start = writer.position()? as usize;
             self.block_offsets.push(block_start);
+            heap_block_offsets.push(block_start);
             writer.write_all(block.writer.get_ref())?;
             
-            // apply previous relocations pointing to current heap and block
-            let all_relocations_to_current = self.all_relocations.extract_if(
-                ..,
-                |(_, _, token)| {
-                    token.block_id as usize == block_id && token.heap_id == heap_id
-                },
-            );
-            
-            for (cur_heap_id, offset, token) in all_relocations_to_current {
-                if cur_heap_id == heap_id {
-                    scoped_writer_pos!(writer);
-                    writer.set_position(offset as u64);
-                    domain.apply_reference(writer, block_start + token.offset as usize)?;
-                } else {
-                    let writer = &mut *self.output_buffers[&cur_heap_id].borrow_mut();
-                    
-                    scoped_writer_pos!(writer);
-                    writer.set_position(offset as u64);
-                    domain.apply_reference(writer, block_start + token.offset as usize)?;
-                }
-            }
-            
-            relocations_from_current.clear();
-            relocations_from_current.extend_from_slice(&block.relocations);
+            self.all_relocations.extend(block.relocations.iter().copied()
+                .map(|(local_offset, byte_size, token)| (block_start + local_offset, byte_size, token)));
+        }
+        
+        Ok(())
+    }
+    
+    /// Patches every collected relocation, regardless of whether it points forwards or backwards
+    pub fn apply_relocations(&mut self, domain: &mut impl WriteDomain) -> Result<Vec<ResolvedRelocation>> {
+        let writer = &mut self.output;
+        scoped_writer_pos!(writer);
+        
+        let mut resolved = Vec::with_capacity(self.all_relocations.len());
+        
+        for &(offset, byte_size, token) in &self.all_relocations {
+            let Some(block_start) = self.heap_block_offsets.get(&token.heap_id)
+                .and_then(|offsets| offsets.get(token.block_id as usize)) else {
+                return Err(anyhow!("Relocation at 0x{offset:x} points to a block that was never written ({token:?})"));
+            };
             
-            // TODO: apply relocations to previously visited heaps and blocks
-            // let all_relocations_to_previous = relocations_from_current.extract_if(
-            //     ..,
-            //     |(_, token)| {
-            //         (token.heap_id == heap_id && (token.block_id as usize) < block_id)
-            //         || self.output_buffers.contains_key(&token.heap_id)
-            //     },
-            // );
-            // drop(all_relocations_to_previous);
+            let points_to = block_start.checked_add(token.offset)
+                .filter(|points_to| byte_size >= 8 || (*points_to as u64) >> (byte_size * 8) == 0)
+                .ok_or_else(|| anyhow!("Relocation at 0x{offset:x} points to 0x{block_start:x} + 0x{:x}, which does not fit into {byte_size} bytes", token.offset))?;
             
-            // push new relocations
-            self.all_relocations.extend(relocations_from_current.iter().copied()
-                .map(|(local_offset, token)| (heap_id, block_start + local_offset, token)));
+            writer.set_position(offset as u64)?;
+            domain.apply_reference(writer, points_to, byte_size)?;
             
+            resolved.push(ResolvedRelocation { at: offset, points_to });
+        }
+        
+        Ok(resolved)
+    }
+    
+    /// Runs the callbacks registered by `WriteHeap::write_deferred` for a heap that was already written out
+    /// and patches in their results. Has to come last so that `FinalLayout::total_len` is accurate
+    pub fn apply_deferred(&mut self, heap_id: HeapID, heap: &mut WriteHeap<WriteCtxWriter>) -> Result<()> {
+        let writer = &mut self.output;
+        let total_len = writer.seek(SeekFrom::End(0))? as usize;
+        
+        let layout = FinalLayout {
+            heap_block_offsets: &self.heap_block_offsets,
+            total_len,
+        };
+        
+        let block_offsets = self.heap_block_offsets.get(&heap_id)
+            .ok_or_else(|| anyhow!("Heap {heap_id:?} was never written"))?;
+        
+        for (block, block_start) in heap.blocks.iter_mut().zip(block_offsets) {
+            for deferred in block.deferred.drain(..) {
+                let bytes = (deferred.compute)(&layout);
+                
+                writer.set_position((block_start + deferred.offset) as u64)?;
+                writer.write_all(&bytes)?;
+            }
         }
         
+        writer.seek(SeekFrom::End(0))?;
         Ok(())
     }
 }
-
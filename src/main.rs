@@ -6,7 +6,7 @@ use vivibin::{
     scoped_reader_pos, CanRead, CanReadVec, CanWrite, CanWriteBox, CanWriteSlice,
     CanWriteSliceWithArgs, CanWriteWithArgs, EndianSpecific, Endianness, HeapCategory, ReadDomain,
     ReadVecFallbackExt, Readable, Reader, SimpleWritable, Writable, WriteCtx, WriteDomain,
-    WriteDomainExt, WriteSliceWithArgsFallbackExt, Writer,
+    WriteBoxFallbackExt, WriteDomainExt, WriteSliceWithArgsFallbackExt, Writer,
 };
 
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
@@ -66,16 +66,6 @@ impl<C: HeapCategory> FormatCgfx<C> {
         reader.read_c_str()
     }
     
-    pub fn write_str(ctx: &mut impl WriteCtx<C>, value: &str) -> Result<()> {
-        let token = ctx.allocate_next_block(None, move |ctx| {
-            ctx.write_c_str(value)?;
-            Ok(())
-        })?;
-        
-        ctx.write_token::<4>(token)?;
-        Ok(())
-    }
-    
     pub fn write_str_new(&mut self, ctx: &mut impl WriteCtx<C>) -> Result<()> {
         0u32.to_writer(ctx, self)?;
         Ok(())
@@ -152,7 +142,7 @@ impl<C: HeapCategory> WriteDomain for FormatCgfx<C> {
     type Pointer = Pointer;
     type Cat = C;
     
-    fn apply_reference(&mut self, writer: &mut impl Writer, heap_offset: usize) -> Result<()> {
+    fn apply_reference(&mut self, writer: &mut impl Writer, heap_offset: usize, _byte_size: usize) -> Result<()> {
         Self::write_relative_ptr(writer, heap_offset.into())
     }
 }
@@ -167,7 +157,7 @@ impl<C: HeapCategory> CanWriteBox<C> for FormatCgfx<C> {
             write_content(self, ctx)
         })?;
         
-        ctx.write_token::<4>(token)
+        self.write_pointer_token(ctx, token)
     }
 }
 
@@ -185,7 +175,7 @@ impl<C: HeapCategory> CanWriteSlice<C> for FormatCgfx<C> {
             }
             Ok(())
         })?;
-        ctx.write_token::<4>(item_ids_token)?;
+        self.write_pointer_token(ctx, item_ids_token)?;
         Ok(())
     }
 }
@@ -222,12 +212,12 @@ struct NewSerialization;
 
 impl<C: HeapCategory> CanWrite<C, str> for FormatCgfx<C> {
     fn write(&mut self, ctx: &mut impl WriteCtx<C>, value: &str) -> Result<()> {
-        Self::write_str(ctx, value)
+        self.write_str_boxed(ctx, value)
     }
 }
 impl<C: HeapCategory> CanWrite<C, String> for FormatCgfx<C> {
     fn write(&mut self, ctx: &mut impl WriteCtx<C>, value: &String) -> Result<()> {
-        Self::write_str(ctx, value)
+        self.write_str_boxed(ctx, value)
     }
 }
 impl<C: HeapCategory> CanWriteWithArgs<C, String, NewSerialization> for FormatCgfx<C> {
@@ -321,7 +311,7 @@ struct Npc {
     // future proc macro?: #[args(BoolSize::U32)]
     is_visible: bool,
     
-    item_ids: std::vec::Vec<u32>,
+    item_ids: Vec<u32>,
     
     child: NewBoxedChild,
 }
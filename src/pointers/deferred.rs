@@ -0,0 +1,78 @@
+use anyhow::Result;
+
+use crate::{scoped_reader_pos, AnyReadable, ReadDomain, Reader};
+
+use super::PointerZero32;
+
+/// A pointer that was read but not followed yet, together with where it was read from
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DeferredPtr {
+    pub field_position: u64,
+    pub pointer: PointerZero32,
+}
+
+impl DeferredPtr {
+    pub fn from_reader(reader: &mut impl Reader, domain: impl ReadDomain) -> Result<Self> {
+        let field_position = reader.position()?;
+        let pointer = PointerZero32::from_reader_any(reader, domain)?;
+        
+        Ok(Self {
+            field_position,
+            pointer,
+        })
+    }
+}
+
+/// Collects pointers while reading a header so they can be followed later, in order of their targets
+#[derive(Clone, Debug, Default)]
+pub struct DeferredPtrs {
+    pointers: Vec<DeferredPtr>,
+}
+
+impl DeferredPtrs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Reads a pointer and adds it to the list, returning its index
+    pub fn read(&mut self, reader: &mut impl Reader, domain: impl ReadDomain) -> Result<usize> {
+        let pointer = DeferredPtr::from_reader(reader, domain)?;
+        self.pointers.push(pointer);
+        Ok(self.pointers.len() - 1)
+    }
+    
+    pub fn push(&mut self, pointer: DeferredPtr) {
+        self.pointers.push(pointer);
+    }
+    
+    pub fn pointers(&self) -> &[DeferredPtr] {
+        &self.pointers
+    }
+    
+    pub fn len(&self) -> usize {
+        self.pointers.len()
+    }
+    
+    pub fn is_empty(&self) -> bool {
+        self.pointers.is_empty()
+    }
+    
+    /// Seeks to every non-null pointer, sorted by target offset, and calls `callback` there.
+    /// The reader's position is restored afterwards.
+    pub fn resolve_all<R: Reader>(mut self, reader: &mut R, mut callback: impl FnMut(&mut R, DeferredPtr) -> Result<()>) -> Result<()> {
+        scoped_reader_pos!(reader);
+        
+        self.pointers.sort_by_key(|pointer| pointer.pointer);
+        
+        for pointer in self.pointers {
+            if pointer.pointer.value() == 0 {
+                continue;
+            }
+            
+            reader.set_position(pointer.pointer)?;
+            callback(reader, pointer)?;
+        }
+        
+        Ok(())
+    }
+}
@@ -1,5 +1,11 @@
+mod deferred;
 mod pointer_zero32;
+mod pointer_zero64;
 mod pointer_nz32;
+mod ptr;
 
+pub use deferred::*;
 pub use pointer_nz32::*;
 pub use pointer_zero32::*;
+pub use pointer_zero64::*;
+pub use ptr::*;
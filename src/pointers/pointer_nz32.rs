@@ -1,86 +1,68 @@
 use alloc::fmt::{self, Debug};
-use core::num::NonZeroU32;
+use core::{
+    num::{NonZeroU32, TryFromIntError},
+    ops::{Add, Sub},
+};
 use std::io::{Read, Write};
 
-use anyhow::{Error, Result};
+use anyhow::{anyhow, Error, Result};
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
 
 use crate::{
-    impl_writable_from_simple, AnyReadable, ReadDomain, Reader, SimpleWritable, WriteDomain, Writer,
+    impl_writable_from_simple, AnyReadable, FixedSize, ReadDomain, Reader, SimpleWritable, WriteDomain, Writer,
 };
 
-// macro_rules! from_type {
-//     ($t:ident, $from:ty) => {
-//         impl From<$from> for $t {
-//             fn from(value: $from) -> Self {
-//                 PointerZero32(value.into())
-//             }
-//         }
-        
-//         impl Add<$from> for $t {
-//             type Output = Self;
-        
-//             fn add(self, rhs: $from) -> Self {
-//                 $t(self.0 + u32::from(rhs))
-//             }
-//         }
-        
-//         impl Sub<$from> for $t {
-//             type Output = Self;
-        
-//             fn sub(self, rhs: $from) -> Self {
-//                 $t(self.0 - u32::from(rhs))
-//             }
-//         }
-//     };
-// }
-
-// macro_rules! from_type_unwrap {
-//     ($t:ident, $from:ty) => {
-//         impl From<$from> for $t {
-//             fn from(value: $from) -> Self {
-//                 PointerZero32(value.try_into().unwrap())
-//             }
-//         }
+// unlike PointerZero32, conversions and arithmetic can fail here (zero or out of range),
+// so these return errors instead of unwrapping
+macro_rules! from_type {
+    ($t:ident, $from:ty) => {
+        impl TryFrom<$from> for $t {
+            type Error = Error;
+            
+            fn try_from(value: $from) -> Result<Self> {
+                $t::from_offset(value as i128)
+            }
+        }
         
-//         impl Add<$from> for $t {
-//             type Output = Self;
+        impl Add<$from> for $t {
+            type Output = Result<Self>;
         
-//             fn add(self, rhs: $from) -> Self {
-//                 // it's beautiful
-//                 $t((i32::try_from(self.0).unwrap() + i32::try_from(rhs).unwrap()).try_into().unwrap())
-//             }
-//         }
+            fn add(self, rhs: $from) -> Result<Self> {
+                $t::from_offset(i128::from(self.0.get()) + rhs as i128)
+            }
+        }
         
-//         impl Sub<$from> for $t {
-//             type Output = Self;
+        impl Sub<$from> for $t {
+            type Output = Result<Self>;
         
-//             fn sub(self, rhs: $from) -> Self {
-//                 $t((i32::try_from(self.0).unwrap() - i32::try_from(rhs).unwrap()).try_into().unwrap())
-//             }
-//         }
-//     };
-// }
-
-// macro_rules! into_type {
-//     ($t:ident, $into:ty) => {
-//         impl From<$t> for $into {
-//             fn from(value: $t) -> Self {
-//                 value.0.into()
-//             }
-//         }
-//     };
-// }
-
-// macro_rules! into_type_unwrap {
-//     ($t:ident, $into:ty) => {
-//         impl From<$t> for $into {
-//             fn from(value: $t) -> Self {
-//                 value.0.try_into().unwrap()
-//             }
-//         }
-//     };
-// }
+            fn sub(self, rhs: $from) -> Result<Self> {
+                $t::from_offset(i128::from(self.0.get()) - rhs as i128)
+            }
+        }
+    };
+}
+
+macro_rules! into_type {
+    ($t:ident, $into:ty) => {
+        impl From<$t> for $into {
+            fn from(value: $t) -> Self {
+                value.0.get().into()
+            }
+        }
+    };
+}
+
+macro_rules! try_into_type {
+    ($t:ident, $into:ty) => {
+        impl TryFrom<$t> for $into {
+            type Error = TryFromIntError;
+            
+            fn try_from(value: $t) -> Result<Self, TryFromIntError> {
+                value.0.get().try_into()
+            }
+        }
+    };
+}
 
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct PointerNonZero32(NonZeroU32);
@@ -128,6 +110,13 @@ impl PointerNonZero32 {
     pub fn value_non_zero(&self) -> NonZeroU32 {
         self.0
     }
+    
+    fn from_offset(value: i128) -> Result<Self> {
+        u32::try_from(value).ok()
+            .and_then(NonZeroU32::new)
+            .map(PointerNonZero32)
+            .ok_or_else(|| anyhow!("Pointer value {value:#x} is out of range for PointerNonZero32"))
+    }
 }
 
 impl AnyReadable for PointerNonZero32 {
@@ -146,6 +135,10 @@ impl<D: WriteDomain> SimpleWritable<D> for PointerNonZero32 {
 
 impl_writable_from_simple!(PointerNonZero32);
 
+impl FixedSize for PointerNonZero32 {
+    const SIZE: usize = 4;
+}
+
 // TODO: how do I allow user defined types to do the same
 // this is only possible becasue Readable and SimpleWritable are defined in the same crate
 impl AnyReadable for Option<PointerNonZero32> {
@@ -165,27 +158,32 @@ impl<D: WriteDomain> SimpleWritable<D> for Option<PointerNonZero32> {
 
 impl_writable_from_simple!(Option<PointerNonZero32>);
 
+impl FixedSize for Option<PointerNonZero32> {
+    const SIZE: usize = 4;
+}
+
 impl Debug for PointerNonZero32 {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.write_fmt(format_args!("Pointer({:#x})", self.0))
     }
 }
 
-// impl Add<Self> for Pointer32 {
-//     type Output = Self;
 
-//     fn add(self, rhs: Self) -> Self {
-//         Pointer32(self.0 + rhs.0)
-//     }
-// }
+impl Add<Self> for PointerNonZero32 {
+    type Output = Result<Self>;
 
-// impl Sub<Self> for Pointer32 {
-//     type Output = Self;
+    fn add(self, rhs: Self) -> Result<Self> {
+        self + rhs.0.get()
+    }
+}
+
+impl Sub<Self> for PointerNonZero32 {
+    type Output = Result<Self>;
 
-//     fn sub(self, rhs: Self) -> Self {
-//         Pointer32(self.0 - rhs.0)
-//     }
-// }
+    fn sub(self, rhs: Self) -> Result<Self> {
+        self - rhs.0.get()
+    }
+}
 
 impl<R: Reader> From<&mut R> for PointerNonZero32 {
     fn from(value: &mut R) -> Self {
@@ -197,17 +195,15 @@ impl<R: Reader> From<&mut R> for PointerNonZero32 {
     }
 }
 
-// TODO: aaaa
-// from_type!(Pointer32, u32);
-
-// from_type_unwrap!(Pointer32, i32);
-// from_type_unwrap!(Pointer32, u64);
-// from_type_unwrap!(Pointer32, i64);
-// from_type_unwrap!(Pointer32, usize);
+from_type!(PointerNonZero32, u32);
+from_type!(PointerNonZero32, i32);
+from_type!(PointerNonZero32, u64);
+from_type!(PointerNonZero32, i64);
+from_type!(PointerNonZero32, usize);
 
-// into_type!(Pointer32, u32);
-// into_type!(Pointer32, u64);
-// into_type!(Pointer32, i64);
+into_type!(PointerNonZero32, u32);
+into_type!(PointerNonZero32, u64);
+into_type!(PointerNonZero32, i64);
 
-// into_type_unwrap!(Pointer32, i32);
-// into_type_unwrap!(Pointer32, usize);
+try_into_type!(PointerNonZero32, i32);
+try_into_type!(PointerNonZero32, usize);
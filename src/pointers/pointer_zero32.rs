@@ -3,10 +3,10 @@ use core::ops::{Add, Sub};
 use std::io::{Read, Seek, Write};
 
 use anyhow::Result;
-use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use byteorder::{BigEndian, LittleEndian, ReadBytesExt, WriteBytesExt};
 
 use crate::{
-    impl_writable_from_simple, AnyReadable, ReadDomain, Reader, SimpleWritable, WriteDomain, Writer,
+    impl_writable_from_simple, AnyReadable, Endianness, FixedSize, ReadDomain, Reader, SimpleWritable, WriteDomain, Writer,
 };
 
 macro_rules! from_type {
@@ -91,7 +91,19 @@ impl PointerZero32 {
     }
     
     pub fn read(reader: &mut impl Read) -> Result<Option<PointerZero32>> {
-        let value = reader.read_u32::<LittleEndian>()?;
+        Self::read_with(reader, Endianness::Little)
+    }
+    
+    pub fn read_relative<R: Read + Seek>(reader: &mut R) -> Result<Option<PointerZero32>> {
+        Self::read_relative_with(reader, Endianness::Little)
+    }
+    
+    pub fn read_with(reader: &mut impl Read, endianness: Endianness) -> Result<Option<PointerZero32>> {
+        let value = if endianness.is_little() {
+            reader.read_u32::<LittleEndian>()?
+        } else {
+            reader.read_u32::<BigEndian>()?
+        };
         
         if value != 0 {
             Ok(Some(PointerZero32(value)))
@@ -100,15 +112,11 @@ impl PointerZero32 {
         }
     }
     
-    pub fn read_relative<R: Read + Seek>(reader: &mut R) -> Result<Option<PointerZero32>> {
+    pub fn read_relative_with<R: Read + Seek>(reader: &mut R, endianness: Endianness) -> Result<Option<PointerZero32>> {
         let reader_pos = reader.stream_position()?;
-        let value = reader.read_u32::<LittleEndian>()?;
+        let value = Self::read_with(reader, endianness)?;
         
-        if value != 0 {
-            Ok(Some(PointerZero32(value) + reader_pos))
-        } else {
-            Ok(None)
-        }
+        Ok(value.map(|value| value + reader_pos))
     }
     
     pub fn write(&self, writer: &mut impl Write) -> Result<()> {
@@ -143,6 +151,32 @@ impl<D: WriteDomain> SimpleWritable<D> for PointerZero32 {
 
 impl_writable_from_simple!(PointerZero32);
 
+impl FixedSize for PointerZero32 {
+    const SIZE: usize = 4;
+}
+
+// zero is the null pointer, like for Option<PointerNonZero32>
+impl AnyReadable for Option<PointerZero32> {
+    fn from_reader_any<R: Reader>(reader: &mut R, domain: impl ReadDomain) -> Result<Self> {
+        let value = u32::from_reader_any(reader, domain)?;
+        Ok((value != 0).then_some(PointerZero32(value)))
+    }
+}
+
+impl<D: WriteDomain> SimpleWritable<D> for Option<PointerZero32> {
+    fn to_writer_simple(&self, ctx: &mut impl Writer, domain: &mut D) -> Result<()> {
+        let value: u32 = self.map(|x| x.0).unwrap_or(0);
+        value.to_writer_simple(ctx, domain)?;
+        Ok(())
+    }
+}
+
+impl_writable_from_simple!(Option<PointerZero32>);
+
+impl FixedSize for Option<PointerZero32> {
+    const SIZE: usize = 4;
+}
+
 impl Debug for PointerZero32 {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.write_fmt(format_args!("Pointer({:#x})", self.0))
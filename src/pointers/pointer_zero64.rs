@@ -0,0 +1,198 @@
+use alloc::fmt::{self, Debug};
+use core::{
+    num::TryFromIntError,
+    ops::{Add, Sub},
+};
+use std::io::{Read, Seek, Write};
+
+use anyhow::{anyhow, Error, Result};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::{
+    impl_writable_from_simple, AnyReadable, FixedSize, ReadDomain, Reader, SimpleWritable, WriteDomain, Writer,
+};
+
+// like PointerNonZero32, arithmetic and conversions from signed types can go out of range,
+// so these return errors instead of unwrapping
+
+macro_rules! from_type {
+    ($t:ident, $from:ty) => {
+        impl From<$from> for $t {
+            fn from(value: $from) -> Self {
+                $t(value.into())
+            }
+        }
+    };
+}
+
+macro_rules! try_from_type {
+    ($t:ident, $from:ty) => {
+        impl TryFrom<$from> for $t {
+            type Error = Error;
+            
+            fn try_from(value: $from) -> Result<Self> {
+                $t::from_offset(value as i128)
+            }
+        }
+    };
+}
+
+macro_rules! arithmetic {
+    ($t:ident, $rhs:ty) => {
+        impl Add<$rhs> for $t {
+            type Output = Result<Self>;
+        
+            fn add(self, rhs: $rhs) -> Result<Self> {
+                $t::from_offset(i128::from(self.0) + rhs as i128)
+            }
+        }
+        
+        impl Sub<$rhs> for $t {
+            type Output = Result<Self>;
+        
+            fn sub(self, rhs: $rhs) -> Result<Self> {
+                $t::from_offset(i128::from(self.0) - rhs as i128)
+            }
+        }
+    };
+}
+
+macro_rules! into_type {
+    ($t:ident, $into:ty) => {
+        impl From<$t> for $into {
+            fn from(value: $t) -> Self {
+                value.0.into()
+            }
+        }
+    };
+}
+
+macro_rules! try_into_type {
+    ($t:ident, $into:ty) => {
+        impl TryFrom<$t> for $into {
+            type Error = TryFromIntError;
+            
+            fn try_from(value: $t) -> Result<Self, TryFromIntError> {
+                value.0.try_into()
+            }
+        }
+    };
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct PointerZero64(u64);
+
+impl PointerZero64 {
+    pub fn new(value: u64) -> PointerZero64 {
+        PointerZero64(value)
+    }
+    
+    pub fn read(reader: &mut impl Read) -> Result<Option<PointerZero64>> {
+        let value = reader.read_u64::<LittleEndian>()?;
+        
+        if value != 0 {
+            Ok(Some(PointerZero64(value)))
+        } else {
+            Ok(None)
+        }
+    }
+    
+    pub fn read_relative<R: Read + Seek>(reader: &mut R) -> Result<Option<PointerZero64>> {
+        let reader_pos = reader.stream_position()?;
+        let value = reader.read_u64::<LittleEndian>()?;
+        
+        if value != 0 {
+            Ok(Some((PointerZero64(value) + reader_pos)?))
+        } else {
+            Ok(None)
+        }
+    }
+    
+    pub fn write(&self, writer: &mut impl Write) -> Result<()> {
+        writer.write_u64::<LittleEndian>(self.0)?;
+        Ok(())
+    }
+    
+    pub fn write_option(pointer: Option<Self>, writer: &mut impl Write) -> Result<()> {
+        if let Some(pointer) = pointer {
+            pointer.write(writer)?;
+        }
+        Ok(())
+    }
+    
+    pub fn value(&self) -> u64 {
+        self.0
+    }
+    
+    /// Pointer to the current position of `reader`
+    pub fn from_position(reader: &mut impl Reader) -> Result<Self> {
+        Ok(PointerZero64(reader.position()?))
+    }
+    
+    fn from_offset(value: i128) -> Result<Self> {
+        u64::try_from(value)
+            .map(PointerZero64)
+            .map_err(|_| anyhow!("Pointer value {value:#x} is out of range for PointerZero64"))
+    }
+}
+
+impl AnyReadable for PointerZero64 {
+    fn from_reader_any<R: Reader>(reader: &mut R, domain: impl ReadDomain) -> Result<Self> {
+        Ok(PointerZero64(u64::from_reader_any(reader, domain)?))
+    }
+}
+
+impl<D: WriteDomain> SimpleWritable<D> for PointerZero64 {
+    fn to_writer_simple(&self, writer: &mut impl Writer, domain: &mut D) -> Result<()> {
+        self.0.to_writer_simple(writer, domain)?;
+        Ok(())
+    }
+}
+
+impl_writable_from_simple!(PointerZero64);
+
+impl FixedSize for PointerZero64 {
+    const SIZE: usize = 8;
+}
+
+impl Debug for PointerZero64 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_fmt(format_args!("Pointer({:#x})", self.0))
+    }
+}
+
+impl Add<Self> for PointerZero64 {
+    type Output = Result<Self>;
+
+    fn add(self, rhs: Self) -> Result<Self> {
+        self + rhs.0
+    }
+}
+
+impl Sub<Self> for PointerZero64 {
+    type Output = Result<Self>;
+
+    fn sub(self, rhs: Self) -> Result<Self> {
+        self - rhs.0
+    }
+}
+
+from_type!(PointerZero64, u32);
+from_type!(PointerZero64, u64);
+
+try_from_type!(PointerZero64, i32);
+try_from_type!(PointerZero64, i64);
+try_from_type!(PointerZero64, usize);
+
+arithmetic!(PointerZero64, u32);
+arithmetic!(PointerZero64, i32);
+arithmetic!(PointerZero64, u64);
+arithmetic!(PointerZero64, i64);
+arithmetic!(PointerZero64, usize);
+
+into_type!(PointerZero64, u64);
+
+try_into_type!(PointerZero64, u32);
+try_into_type!(PointerZero64, i32);
+try_into_type!(PointerZero64, i64);
+try_into_type!(PointerZero64, usize);
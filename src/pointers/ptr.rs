@@ -0,0 +1,110 @@
+use alloc::fmt::{self, Debug};
+use core::{hash::{Hash, Hasher}, marker::PhantomData};
+
+use anyhow::{anyhow, Result};
+
+use crate::{
+    impl_writable_from_simple, scoped_reader_pos, AnyReadable, FixedSize, ReadDomain, Readable, Reader, SimpleWritable, WriteDomain, Writer,
+};
+
+use super::PointerZero32;
+
+/// A `PointerZero32` that remembers the type of the value it points to
+pub struct Ptr<T>(PointerZero32, PhantomData<T>);
+
+impl<T> Ptr<T> {
+    pub fn new(pointer: PointerZero32) -> Self {
+        Ptr(pointer, PhantomData)
+    }
+    
+    pub fn pointer(&self) -> PointerZero32 {
+        self.0
+    }
+    
+    pub fn is_null(&self) -> bool {
+        self.0.value() == 0
+    }
+    
+    /// Reads the value this pointer points to, restoring the reader's position afterwards
+    pub fn follow<R: Reader, D: ReadDomain>(self, reader: &mut R, domain: D) -> Result<T>
+    where
+        T: Readable<D>,
+    {
+        self.follow_nullable(reader, domain)?
+            .ok_or_else(|| anyhow!("Tried to follow null pointer"))
+    }
+    
+    /// Like `follow`, but returns `None` for a null pointer
+    pub fn follow_nullable<R: Reader, D: ReadDomain>(self, reader: &mut R, domain: D) -> Result<Option<T>>
+    where
+        T: Readable<D>,
+    {
+        if self.is_null() {
+            return Ok(None);
+        }
+        
+        scoped_reader_pos!(reader);
+        reader.set_position(self.0)?;
+        T::from_reader(reader, domain).map(Some)
+    }
+}
+
+impl<T> AnyReadable for Ptr<T> {
+    fn from_reader_any<R: Reader>(reader: &mut R, domain: impl ReadDomain) -> Result<Self> {
+        Ok(Ptr::new(PointerZero32::from_reader_any(reader, domain)?))
+    }
+}
+
+impl<T, D: WriteDomain> SimpleWritable<D> for Ptr<T> {
+    fn to_writer_simple(&self, writer: &mut impl Writer, domain: &mut D) -> Result<()> {
+        self.0.to_writer_simple(writer, domain)
+    }
+}
+
+impl_writable_from_simple!(impl [T] Ptr<T>);
+
+impl<T> FixedSize for Ptr<T> {
+    const SIZE: usize = PointerZero32::SIZE;
+}
+
+// implemented by hand so that they don't require T to implement them
+
+impl<T> Clone for Ptr<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Ptr<T> {}
+
+impl<T> Default for Ptr<T> {
+    fn default() -> Self {
+        Ptr::new(PointerZero32::default())
+    }
+}
+
+impl<T> PartialEq for Ptr<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+}
+
+impl<T> Eq for Ptr<T> {}
+
+impl<T> Hash for Ptr<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.hash(state);
+    }
+}
+
+impl<T> Debug for Ptr<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_fmt(format_args!("Ptr({:#x})", self.0.value()))
+    }
+}
+
+impl<T> From<PointerZero32> for Ptr<T> {
+    fn from(value: PointerZero32) -> Self {
+        Ptr::new(value)
+    }
+}
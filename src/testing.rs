@@ -0,0 +1,35 @@
+//! Helpers for checking parsers in test suites, also meant for crates using vivibin
+
+use std::io::Cursor;
+
+use anyhow::{anyhow, Result};
+
+use crate::{ReadDomain, Readable, Writable, WriteDomain, WriteDomainExt};
+
+/// Parses `bytes` as a `T` and writes it out again, failing unless the output is identical to the input
+pub fn roundtrip_eq<D, T>(bytes: &[u8], mut domain: D) -> Result<()>
+where
+    D: ReadDomain + WriteDomain,
+    T: Readable<D> + Writable<D::Cat, D>,
+{
+    let value = T::from_reader(&mut Cursor::new(bytes), domain)?;
+    
+    let mut ctx = D::new_ctx();
+    value.to_writer(&mut ctx, &mut domain)?;
+    let written = ctx.to_buffer(&mut domain, None)?;
+    
+    if let Some(offset) = bytes.iter().zip(&written).position(|(expected, actual)| expected != actual) {
+        let end = (offset + 16).min(bytes.len()).min(written.len());
+        
+        return Err(anyhow!(
+            "Roundtrip mismatch at offset 0x{offset:x}:\n  read:    {:02x?}\n  written: {:02x?}",
+            &bytes[offset..end], &written[offset..end],
+        ));
+    }
+    
+    if bytes.len() != written.len() {
+        return Err(anyhow!("Roundtrip mismatch: read 0x{:x} bytes, but wrote 0x{:x}", bytes.len(), written.len()));
+    }
+    
+    Ok(())
+}
@@ -1,18 +1,29 @@
-use std::io::{Seek, SeekFrom};
+use core::any::Any;
+use std::io::{self, Read, Seek, SeekFrom};
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
+
+use crate::{WriteHeap, Writer};
+
+/// The hasher used by the heaps of `WriteCtxImpl`. Not public, so that enabling `fxhash` doesn't change any public types
+#[cfg(feature = "fxhash")]
+pub(crate) type BuildHasher = rustc_hash::FxBuildHasher;
+#[cfg(all(feature = "hashbrown", not(feature = "fxhash")))]
+pub(crate) type BuildHasher = hashbrown::DefaultHashBuilder;
+#[cfg(not(any(feature = "hashbrown", feature = "fxhash")))]
+pub(crate) type BuildHasher = std::hash::RandomState;
 
 #[cfg(feature = "hashbrown")]
 pub use hashbrown::HashMap;
 #[cfg(not(feature = "hashbrown"))]
 pub use std::collections::HashMap;
 
-pub struct SeekGuard<'a, R: Seek> {
+pub struct SeekGuard<'a, R: Seek + ?Sized> {
     pub seek: &'a mut R,
     start_pos: u64,
 }
 
-impl<'a, R: Seek> SeekGuard<'a, R> {
+impl<'a, R: Seek + ?Sized> SeekGuard<'a, R> {
     pub fn new(reader: &'a mut R) -> Result<Self> {
         let start_pos = reader.stream_position()?;
         
@@ -23,13 +34,225 @@ impl<'a, R: Seek> SeekGuard<'a, R> {
     }
 }
 
-impl<R: Seek> Drop for SeekGuard<'_, R> {
+impl<R: Seek + ?Sized> Drop for SeekGuard<'_, R> {
     fn drop(&mut self) {
         self.seek.seek(SeekFrom::Start(self.start_pos)).unwrap();
     }
 }
 
 
+/// Restores the current block of a `WriteHeap` when dropped
+pub struct HeapGuard<'a, W: Writer> {
+    pub heap: &'a mut WriteHeap<W>,
+    current_block: usize,
+}
+
+impl<'a, W: Writer> HeapGuard<'a, W> {
+    pub fn new(heap: &'a mut WriteHeap<W>) -> Self {
+        let current_block = heap.current_block;
+        
+        Self {
+            heap,
+            current_block,
+        }
+    }
+}
+
+impl<W: Writer> Drop for HeapGuard<'_, W> {
+    fn drop(&mut self) {
+        self.heap.current_block = self.current_block;
+    }
+}
+
+/// Reader restricted to a section of the underlying reader. The end of the section behaves like the end of the stream,
+/// so reads get cut short there and `read_exact` fails with `UnexpectedEof`, while reads before its start error.
+/// Positions are still absolute, seeking from the end seeks from the end of the section.
+pub struct BoundedReader<'a, R: Read + Seek + ?Sized> {
+    reader: &'a mut R,
+    start: u64,
+    limit: u64,
+}
+
+impl<'a, R: Read + Seek + ?Sized> BoundedReader<'a, R> {
+    /// Restricts reading to the next `length` bytes, starting at the current position
+    pub fn new(reader: &'a mut R, length: u64) -> Result<Self> {
+        let start = reader.stream_position()?;
+        let limit = start.checked_add(length)
+            .ok_or_else(|| anyhow!("Section of 0x{length:x} bytes at 0x{start:x} extends past the end of the address space"))?;
+        
+        Ok(Self {
+            reader,
+            start,
+            limit,
+        })
+    }
+    
+    pub fn start(&self) -> u64 {
+        self.start
+    }
+    
+    pub fn limit(&self) -> u64 {
+        self.limit
+    }
+}
+
+impl<R: Read + Seek + ?Sized> Read for BoundedReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let position = self.reader.stream_position()?;
+        
+        if position < self.start {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Read at 0x{position:x} is before the start of section 0x{:x}..0x{:x}",
+                    self.start, self.limit,
+                ),
+            ));
+        }
+        
+        let remaining = self.limit.saturating_sub(position);
+        let size = buf.len().min(usize::try_from(remaining).unwrap_or(usize::MAX));
+        self.reader.read(&mut buf[..size])
+    }
+}
+
+impl<R: Read + Seek + ?Sized> Seek for BoundedReader<'_, R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match pos {
+            SeekFrom::End(offset) => {
+                let position = self.limit.checked_add_signed(offset)
+                    .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Invalid seek to a negative position"))?;
+                self.reader.seek(SeekFrom::Start(position))
+            },
+            pos => self.reader.seek(pos),
+        }
+    }
+}
+
+/// Reader over a byte slice, like `Cursor<&[u8]>` but with a few extra helpers
+#[derive(Clone, Copy, Debug)]
+pub struct SliceReader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> SliceReader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self {
+            bytes,
+            offset: 0,
+        }
+    }
+    
+    pub fn bytes(&self) -> &'a [u8] {
+        self.bytes
+    }
+    
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+    
+    /// Number of bytes left to read, zero if the offset is past the end
+    pub fn remaining(&self) -> usize {
+        self.bytes.len().saturating_sub(self.offset)
+    }
+    
+    /// The bytes left to read
+    pub fn remaining_bytes(&self) -> &'a [u8] {
+        &self.bytes[self.offset.min(self.bytes.len())..]
+    }
+}
+
+impl Read for SliceReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = self.remaining_bytes();
+        let size = buf.len().min(remaining.len());
+        
+        buf[..size].copy_from_slice(&remaining[..size]);
+        self.offset += size;
+        Ok(size)
+    }
+}
+
+impl Seek for SliceReader<'_> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => (self.bytes.len() as u64).checked_add_signed(offset),
+            SeekFrom::Current(offset) => (self.offset as u64).checked_add_signed(offset),
+        };
+        
+        let position = position
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Invalid seek to a negative position"))?;
+        
+        self.offset = usize::try_from(position)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "Seek position does not fit into usize"))?;
+        Ok(position)
+    }
+}
+
+/// Reader over a read-only memory mapped file, for parsing large files without reading them into memory first
+#[cfg(feature = "mmap")]
+pub struct MmapReader {
+    mmap: memmap2::Mmap,
+    offset: usize,
+}
+
+#[cfg(feature = "mmap")]
+impl MmapReader {
+    pub fn open(path: impl AsRef<std::path::Path>) -> Result<Self> {
+        let file = std::fs::File::open(path)?;
+        
+        // SAFETY: the map is read-only, the file being modified by another process while it is mapped
+        // is the same caveat every user of memory mapped files has to accept
+        let mmap = unsafe { memmap2::Mmap::map(&file)? };
+        
+        Ok(Self {
+            mmap,
+            offset: 0,
+        })
+    }
+    
+    pub fn bytes(&self) -> &[u8] {
+        &self.mmap
+    }
+    
+    /// Borrows the remaining content as a `SliceReader`
+    pub fn as_slice_reader(&self) -> SliceReader<'_> {
+        let mut reader = SliceReader::new(&self.mmap);
+        reader.offset = self.offset;
+        reader
+    }
+}
+
+#[cfg(feature = "mmap")]
+impl Read for MmapReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut reader = self.as_slice_reader();
+        let size = reader.read(buf)?;
+        self.offset = reader.offset;
+        Ok(size)
+    }
+}
+
+#[cfg(feature = "mmap")]
+impl Seek for MmapReader {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let mut reader = self.as_slice_reader();
+        let position = reader.seek(pos)?;
+        self.offset = reader.offset;
+        Ok(position)
+    }
+}
+
+/// Safely converts a value read as `U` into the requested type `T`, returning `None`
+/// if they are not the same type. Useful for dispatching on `T` inside generic domain impls.
+pub fn downcast_read<T: 'static, U: 'static>(value: U) -> Option<T> {
+    let mut value = Some(value);
+    (&mut value as &mut dyn Any).downcast_mut::<Option<T>>()?.take()
+}
+
+
 #[macro_export]
 macro_rules! scoped_reader_pos {
     ($reader:ident) => {
@@ -46,3 +269,11 @@ macro_rules! scoped_writer_pos {
     };
 }
 
+
+#[macro_export]
+macro_rules! scoped_heap_block {
+    ($heap:ident) => {
+        let guard = $crate::util::HeapGuard::new($heap);
+        let $heap = &mut *guard.heap;
+    };
+}
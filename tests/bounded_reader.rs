@@ -0,0 +1,38 @@
+use std::io::{Cursor, Read};
+
+use anyhow::Result;
+use vivibin::{
+    util::{BoundedReader, SliceReader},
+    Reader,
+};
+
+#[test]
+fn length_past_address_space_is_an_error() -> Result<()> {
+    let mut reader = Cursor::new([0u8; 8]);
+    reader.set_position(4);
+    
+    assert!(BoundedReader::new(&mut reader, u64::MAX).is_err());
+    Ok(())
+}
+
+#[test]
+fn reads_are_cut_short_at_the_limit() -> Result<()> {
+    let mut reader = Cursor::new([1u8, 2, 3, 4, 5, 6, 7, 8]);
+    reader.set_position(2);
+    
+    let mut bounded = BoundedReader::new(&mut reader, 3)?;
+    let mut buf = [0; 8];
+    assert_eq!(bounded.read(&mut buf)?, 3);
+    assert_eq!(&buf[..3], &[3, 4, 5]);
+    assert_eq!(bounded.read(&mut buf)?, 0);
+    Ok(())
+}
+
+#[test]
+fn bytes_remaining_is_callable_on_slice_readers() -> Result<()> {
+    let mut reader = SliceReader::new(&[1, 2, 3]);
+    
+    assert_eq!(reader.bytes_remaining()?, 3);
+    assert_eq!(reader.remaining(), 3);
+    Ok(())
+}
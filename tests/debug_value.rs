@@ -0,0 +1,38 @@
+use core::num::NonZeroU32;
+
+use vivibin::{
+    debug_value::{DebugValue, ToDebugValue},
+    pointers::{PointerZero32, Ptr},
+    VivibinDebug,
+};
+
+#[derive(VivibinDebug)]
+struct Everything {
+    pair: (u8, u8),
+    size: usize,
+    non_zero: NonZeroU32,
+    unit: (),
+    pointer: PointerZero32,
+    typed: Ptr<u32>,
+}
+
+#[test]
+fn derive_accepts_all_readable_field_types() {
+    let value = Everything {
+        pair: (1, 2),
+        size: 3,
+        non_zero: NonZeroU32::new(4).unwrap(),
+        unit: (),
+        pointer: PointerZero32::new(5),
+        typed: Ptr::new(PointerZero32::new(6)),
+    };
+    
+    assert_eq!(value.to_debug_value(), DebugValue::Map(vec![
+        ("pair".to_owned(), DebugValue::Seq(vec![DebugValue::Int(1), DebugValue::Int(2)])),
+        ("size".to_owned(), DebugValue::Int(3)),
+        ("non_zero".to_owned(), DebugValue::Int(4)),
+        ("unit".to_owned(), DebugValue::Null),
+        ("pointer".to_owned(), DebugValue::Int(5)),
+        ("typed".to_owned(), DebugValue::Int(6)),
+    ]));
+}
@@ -0,0 +1,109 @@
+use std::io::Cursor;
+
+use anyhow::Result;
+use vivibin::{
+    testing::roundtrip_eq, CanWriteBox, EndianAware, EndianSpecific, Endianness, ReadDomain, ReadDomainExt,
+    Readable, Reader, Writable, WriteCtx, WriteDomain, WriteDomainExt, Writer,
+};
+
+/// Domain with absolute u32 pointers in a byte order that can be overridden
+#[derive(Clone, Copy)]
+struct Absolute {
+    endian: Endianness,
+}
+
+impl EndianSpecific for Absolute {
+    fn endianness(&self) -> Endianness {
+        self.endian
+    }
+    
+    fn set_endianness(&mut self, endianness: Endianness) {
+        self.endian = endianness;
+    }
+}
+
+impl ReadDomain for Absolute {
+    type Pointer = u32;
+    
+    fn read_box_nullable<T, R: Reader>(self, reader: &mut R, read_content: impl FnOnce(&mut R) -> Result<T>) -> Result<Option<T>> {
+        self.read_box_at_absolute(reader, read_content)
+    }
+}
+
+impl WriteDomain for Absolute {
+    type Pointer = u32;
+    type Cat = ();
+    
+    fn apply_reference(&mut self, writer: &mut impl Writer, heap_offset: usize, byte_size: usize) -> Result<()> {
+        writer.write_uint(heap_offset as u64, byte_size, self.endian)
+    }
+}
+
+impl CanWriteBox<()> for Absolute {
+    fn write_box_of<W: WriteCtx<()>>(
+        &mut self,
+        ctx: &mut W,
+        write_content: impl FnOnce(&mut Self, &mut W::InnerCtx<'_>) -> Result<()>,
+    ) -> Result<()> {
+        let token = ctx.allocate_next_block(None, |ctx| write_content(self, ctx))?;
+        self.write_pointer_token(ctx, token)
+    }
+}
+
+#[derive(Debug, PartialEq, Readable, Writable)]
+struct Leaf {
+    value: u16,
+}
+
+#[derive(Debug, PartialEq, Readable, Writable)]
+struct Node {
+    a: u32,
+    #[boxed]
+    child: Option<Box<Leaf>>,
+}
+
+const LITTLE: &[u8] = &[1, 0, 0, 0, 8, 0, 0, 0, 0x34, 0x12];
+const BIG: &[u8] = &[0, 0, 0, 1, 0, 0, 0, 8, 0x12, 0x34];
+
+fn expected_node() -> Node {
+    Node {
+        a: 1,
+        child: Some(Box::new(Leaf { value: 0x1234 })),
+    }
+}
+
+#[test]
+fn override_reaches_pointers_on_read() -> Result<()> {
+    let domain = Absolute { endian: Endianness::Little };
+    
+    let little = Node::from_reader(&mut Cursor::new(LITTLE), EndianAware::new(domain, Endianness::Little))?;
+    let big = Node::from_reader(&mut Cursor::new(BIG), EndianAware::new(domain, Endianness::Big))?;
+    
+    assert_eq!(little, expected_node());
+    assert_eq!(big, expected_node());
+    Ok(())
+}
+
+#[test]
+fn override_reaches_pointers_on_write() -> Result<()> {
+    for (endian, expected) in [(Endianness::Little, LITTLE), (Endianness::Big, BIG)] {
+        let mut domain = EndianAware::new(Absolute { endian: Endianness::Little }, endian);
+        
+        let mut ctx = Absolute::new_ctx();
+        expected_node().to_writer(&mut ctx, &mut domain)?;
+        assert_eq!(ctx.to_buffer(&mut domain, None)?, expected);
+        
+        // the inner domain gets its own byte order back
+        assert_eq!(domain.inner.endian, Endianness::Little);
+    }
+    Ok(())
+}
+
+#[test]
+fn endian_aware_roundtrips_both_byte_orders() -> Result<()> {
+    let domain = Absolute { endian: Endianness::Little };
+    
+    roundtrip_eq::<_, Node>(LITTLE, EndianAware::new(domain, Endianness::Little))?;
+    roundtrip_eq::<_, Node>(BIG, EndianAware::new(domain, Endianness::Big))?;
+    Ok(())
+}
@@ -0,0 +1,13 @@
+use vivibin::flags::FlagsBuilder;
+
+#[test]
+fn bits_are_set_and_cleared() {
+    let flags = FlagsBuilder::new().bit(0, true).bit(63, true).bit(0, false);
+    assert_eq!(flags.bits(), 1 << 63);
+}
+
+#[test]
+#[should_panic(expected = "out of range")]
+fn bit_out_of_range_panics() {
+    let _ = FlagsBuilder::new().bit(64, true);
+}
@@ -0,0 +1,18 @@
+use anyhow::Result;
+use vivibin::pointers::PointerZero64;
+
+#[test]
+fn pointer_zero64_conversions_fail_out_of_range() -> Result<()> {
+    assert!(PointerZero64::try_from(-1i32).is_err());
+    assert!(u32::try_from(PointerZero64::new(1 << 32)).is_err());
+    assert_eq!(u32::try_from(PointerZero64::new(8))?, 8);
+    Ok(())
+}
+
+#[test]
+fn pointer_zero64_arithmetic_fails_out_of_range() -> Result<()> {
+    assert!((PointerZero64::new(4) - 8u32).is_err());
+    assert!((PointerZero64::new(u64::MAX) + 1u32).is_err());
+    assert_eq!((PointerZero64::new(4) + -4i64)?, PointerZero64::new(0));
+    Ok(())
+}
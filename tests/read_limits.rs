@@ -0,0 +1,74 @@
+use std::io::Cursor;
+
+use anyhow::Result;
+use vivibin::{EndianSpecific, Endianness, ReadDomain, ReadDomainExt, ReadLimits, Readable, Reader};
+
+/// Little endian domain that refuses allocations over 16 bytes
+#[derive(Clone, Copy)]
+struct Limited;
+
+impl EndianSpecific for Limited {
+    fn endianness(&self) -> Endianness {
+        Endianness::Little
+    }
+}
+
+impl ReadDomain for Limited {
+    type Pointer = u32;
+    
+    fn read_box_nullable<T, R: Reader>(self, reader: &mut R, read_content: impl FnOnce(&mut R) -> Result<T>) -> Result<Option<T>> {
+        self.read_box_at_absolute(reader, read_content)
+    }
+    
+    fn read_limits(&self) -> ReadLimits {
+        ReadLimits::new(16)
+    }
+}
+
+#[derive(Debug, Readable)]
+struct Counted {
+    count: u32,
+    #[count = "count"]
+    values: Vec<u8>,
+}
+
+#[test]
+fn bogus_huge_length_errors() {
+    let bytes = [0xFF, 0xFF, 0xFF, 0x7F];
+    
+    let result = Limited.read_len_prefixed_vec_of::<4, u8, _>(&mut Cursor::new(bytes), |reader| u8::from_reader(reader, Limited));
+    let error = result.unwrap_err();
+    assert!(error.to_string().contains("exceeds the limit of 16 bytes"), "{error}");
+}
+
+#[test]
+fn bogus_huge_count_field_errors() {
+    let bytes = [0xFF, 0xFF, 0xFF, 0x7F, 1, 2, 3];
+    
+    let error = Counted::from_reader(&mut Cursor::new(bytes), Limited).unwrap_err();
+    assert!(error.to_string().contains("exceeds the limit of 16 bytes"), "{error}");
+}
+
+#[test]
+fn count_field_within_limit_is_read() -> Result<()> {
+    let bytes = [3, 0, 0, 0, 1, 2, 3];
+    
+    let counted = Counted::from_reader(&mut Cursor::new(bytes), Limited)?;
+    assert_eq!(counted.count, 3);
+    assert_eq!(counted.values, [1, 2, 3]);
+    Ok(())
+}
+
+#[test]
+fn read_until_eof_respects_limit() {
+    let bytes = [0u8; 64];
+    
+    let error = Limited.read_until_eof::<u8, _>(&mut Cursor::new(bytes)).unwrap_err();
+    assert!(error.to_string().contains("exceeds the limit of 16 bytes"), "{error}");
+}
+
+#[test]
+fn read_until_eof_rejects_zero_sized_elements() {
+    let error = Limited.read_until_eof::<(), _>(&mut Cursor::new([0u8; 4])).unwrap_err();
+    assert!(error.to_string().contains("didn't consume any bytes"), "{error}");
+}
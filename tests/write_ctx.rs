@@ -0,0 +1,153 @@
+use std::io::Write;
+
+use anyhow::Result;
+use vivibin::{Endianness, EndianSpecific, HeapCategory, ResolvedRelocation, WriteCtx, WriteCtxImpl, WriteDomain, Writer};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+enum Cat {
+    #[default]
+    Main,
+    Strings,
+    Data,
+}
+
+impl HeapCategory for Cat {}
+
+/// Little endian domain with absolute pointers
+struct Le;
+
+impl EndianSpecific for Le {
+    fn endianness(&self) -> Endianness {
+        Endianness::Little
+    }
+}
+
+impl WriteDomain for Le {
+    type Pointer = u32;
+    type Cat = Cat;
+    
+    fn apply_reference(&mut self, writer: &mut impl Writer, heap_offset: usize, byte_size: usize) -> Result<()> {
+        writer.write_uint(heap_offset as u64, byte_size, Endianness::Little)
+    }
+}
+
+fn read_u32(buffer: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(buffer[offset..offset + 4].try_into().unwrap())
+}
+
+#[test]
+fn dedup_writes_identical_blocks_once() -> Result<()> {
+    let mut ctx = WriteCtxImpl::<Cat>::new();
+    
+    let first = ctx.allocate_next_block_dedup(Some(Cat::Strings), |ctx| ctx.write_c_str("position"))?;
+    let second = ctx.allocate_next_block_dedup(Some(Cat::Strings), |ctx| ctx.write_c_str("position"))?;
+    let other = ctx.allocate_next_block_dedup(Some(Cat::Strings), |ctx| ctx.write_c_str("normal"))?;
+    
+    assert_eq!(first, second);
+    assert_ne!(first, other);
+    
+    ctx.write_token::<4>(first)?;
+    ctx.write_token::<4>(second)?;
+    
+    let buffer = ctx.to_buffer(&mut Le, None)?;
+    assert_eq!(&buffer[8..], b"position\0normal\0");
+    assert_eq!(read_u32(&buffer, 0), 8);
+    assert_eq!(read_u32(&buffer, 4), 8);
+    Ok(())
+}
+
+#[test]
+fn cyclic_pointers_are_patched_in_both_directions() -> Result<()> {
+    let mut ctx = WriteCtxImpl::<Cat>::new();
+    
+    // A points forwards to B, B points backwards to A
+    let a = ctx.allocate_next_block(None, |ctx| {
+        let a = ctx.current_heap_token()?;
+        let b = ctx.allocate_next_block(None, |ctx| ctx.write_token::<4>(a))?;
+        ctx.write_token::<4>(b)
+    })?;
+    ctx.write_token::<4>(a)?;
+    
+    let buffer = ctx.to_buffer(&mut Le, None)?;
+    assert_eq!(buffer.len(), 12);
+    assert_eq!(read_u32(&buffer, 0), 4);
+    assert_eq!(read_u32(&buffer, 4), 8);
+    assert_eq!(read_u32(&buffer, 8), 4);
+    Ok(())
+}
+
+#[test]
+fn nested_contexts_restore_their_heaps() -> Result<()> {
+    let mut ctx = WriteCtxImpl::<Cat>::new();
+    ctx.write_all(b"m")?;
+    
+    ctx.allocate_next_block(Some(Cat::Strings), |ctx| {
+        ctx.write_all(b"s1")?;
+        ctx.allocate_next_block(Some(Cat::Data), |ctx| Ok(ctx.write_all(b"d")?))?;
+        ctx.write_all(b"s2")?;
+        Ok(())
+    })?;
+    
+    ctx.write_all(b"m")?;
+    
+    assert_eq!(ctx.heap(&Cat::Main).unwrap().total_len(), 2);
+    assert_eq!(ctx.heap(&Cat::Strings).unwrap().total_len(), 4);
+    assert_eq!(ctx.heap(&Cat::Data).unwrap().total_len(), 1);
+    
+    let buffer = ctx.to_buffer(&mut Le, None)?;
+    assert_eq!(buffer, b"mms1s2d");
+    Ok(())
+}
+
+#[test]
+fn relocation_overflow_is_an_error() -> Result<()> {
+    let mut ctx = WriteCtxImpl::<Cat>::new();
+    
+    // the target block lands after the padding, out of reach of a 2 byte pointer
+    let target = ctx.allocate_next_block(None, |ctx| Ok(ctx.write_all(b"target")?))?;
+    ctx.write_token::<2>(target)?;
+    ctx.write_all(&[0; 0x10000])?;
+    
+    let Err(error) = ctx.to_buffer(&mut Le, None) else {
+        panic!("expected relocation to 0x10002 to overflow");
+    };
+    assert!(error.to_string().contains("does not fit into 2 bytes"), "{error}");
+    Ok(())
+}
+
+#[test]
+fn token_written_at_multiple_sites() -> Result<()> {
+    let mut ctx = WriteCtxImpl::<Cat>::new();
+    
+    let target = ctx.allocate_next_block(Some(Cat::Strings), |ctx| ctx.write_c_str("str"))?;
+    ctx.write_token::<4>(target)?;
+    ctx.allocate_next_block(None, |ctx| ctx.write_token::<4>(target))?;
+    
+    let (buffer, mut relocations) = ctx.to_buffer_with_relocations(&mut Le)?;
+    assert_eq!(&buffer[8..], b"str\0");
+    assert_eq!(read_u32(&buffer, 0), 8);
+    assert_eq!(read_u32(&buffer, 4), 8);
+    
+    relocations.sort_by_key(|relocation| relocation.at);
+    assert_eq!(relocations, [
+        ResolvedRelocation { at: 0, points_to: 8 },
+        ResolvedRelocation { at: 4, points_to: 8 },
+    ]);
+    Ok(())
+}
+
+#[test]
+fn nested_contexts_share_the_dedup_pool() -> Result<()> {
+    let mut ctx = WriteCtxImpl::<Cat>::new();
+    
+    let outer = ctx.allocate_next_block_dedup(Some(Cat::Strings), |ctx| ctx.write_c_str("shared"))?;
+    let mut inner = None;
+    ctx.allocate_next_block(None, |ctx| {
+        inner = Some(ctx.allocate_next_block_dedup(Some(Cat::Strings), |ctx| ctx.write_c_str("shared"))?);
+        Ok(())
+    })?;
+    
+    assert_eq!(inner, Some(outer));
+    assert_eq!(ctx.heap(&Cat::Strings).unwrap().total_len(), 7);
+    Ok(())
+}
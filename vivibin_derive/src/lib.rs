@@ -1,22 +1,69 @@
-use proc_macro2::{Span, TokenStream};
+use proc_macro2::{Span, TokenStream, TokenTree};
 use quote::{format_ident, quote};
 use syn::{
-    parse_macro_input, AngleBracketedGenericArguments, Data, DataStruct, DeriveInput,
-    GenericArgument, Ident, Meta, PathArguments, Type, TypePath,
+    parse_macro_input, parse_quote, AngleBracketedGenericArguments, Attribute, Data, DataEnum,
+    DeriveInput, Expr, ExprLit, Fields, GenericArgument, Ident, Lit, LitByteStr, Member, Meta, PathArguments,
+    Type, TypePath,
 };
 
-struct NamedField<'a> {
-    name: &'a Ident,
+struct Field<'a> {
+    member: Member,
     ty: &'a Type,
     explicit_require_domain: bool,
+    /// #[pad(n)], bytes skipped on read and zero-filled on write before the field
+    pad: Option<Expr>,
+    /// #[align(n)], aligns the field to a multiple of `n` in the whole file (not relative to the struct's start),
+    /// after #[pad(n)] is applied. On write, the struct's heap block gets aligned to `n` as well,
+    /// so that the padding is the same as on read once the block is placed in the output
+    align: Option<Expr>,
+    /// #[args(expr)], reads the field through ReadableWithArgs and writes it through WritableWithArgs
+    args: Option<Expr>,
+    /// #[boxed], the field is a Box<T> or Option<Box<T>> stored behind a pointer
+    boxed: bool,
+    /// #[count = "field"], the field is a Vec<T> stored inline whose length is held by an earlier field
+    count: Option<Member>,
+    /// #[endian(big)] or #[endian(little)], reads and writes the field with a different endianness than the domain's.
+    /// Goes through `EndianAware`, so pointers and counts only follow it if the domain implements
+    /// `EndianSpecific::set_endianness`, and pointers are still patched in the byte order of the domain passed to `to_buffer`
+    endian: Option<TokenStream>,
+    /// #[skip], the field isn't part of the binary layout and is initialized with Default on read
+    skip: bool,
+    /// #[read_if(expr)], the field is only present if `expr` holds and is initialized with Default on read otherwise
+    read_if: Option<ReadIf>,
 }
 
-impl NamedField<'_> {
+/// A #[read_if(expr)] condition and the earlier fields it references by name
+struct ReadIf {
+    condition: Expr,
+    dependencies: Vec<Member>,
+}
+
+impl<'a> Field<'a> {
+    fn var_name(&self) -> Ident {
+        Self::member_var_name(&self.member)
+    }
+    
+    fn member_var_name(member: &Member) -> Ident {
+        match member {
+            Member::Named(name) => {
+                let name_string = name.to_string();
+                format_ident!("_{}", name_string.strip_prefix("r#").unwrap_or(&name_string))
+            },
+            Member::Unnamed(index) => format_ident!("_{}", index.index),
+        }
+    }
+    
     fn write_read_statement(&self, domain: &Ident, reader: &Ident, vec_required: &mut bool, required_domain_impls: &[&Type]) -> (Ident, TokenStream) {
-        let NamedField { name, ty, .. } = *self;
+        let Field { ty, .. } = *self;
         
-        let name_string = name.to_string();
-        let name = format_ident!("_{}", name_string.strip_prefix("r#").unwrap_or(&name_string));
+        let name = self.var_name();
+        
+        if self.skip {
+            let tokens = quote! {
+                let #name: #ty = ::core::default::Default::default();
+            };
+            return (name, tokens);
+        }
         
         let inner_vec_type = Self::get_vec_inner_type(ty);
         
@@ -24,67 +71,222 @@ impl NamedField<'_> {
         let explicit_read_impl = required_domain_impls.iter().copied()
             .any(|current| current == ty);
         
-        let tokens = match (inner_vec_type, explicit_read_impl) {
-            (None, true) => quote! {
-                let #name: #ty = ::vivibin::CanRead::<#ty>::read(#domain, #reader)?;
-            },
-            (None, false) => quote! {
-                let #name: #ty = ::vivibin::Readable::from_reader(#reader, #domain)?;
-            },
-            (Some(inner_ty), true) => {
-                *vec_required = true;
-                quote! {
-                    let #name: #ty = ::vivibin::ReadVecExt::read_std_vec::<#inner_ty, R>(#domain, #reader)?;
-                }
-            },
-            (Some(inner_ty), false) => {
-                *vec_required = true;
-                quote! {
-                    let #name: #ty = ::vivibin::ReadVecFallbackExt::read_std_vec_fallback::<#inner_ty, R>(#domain, #reader)?;
-                }
+        let tokens = if let Some(args) = &self.args {
+            quote! {
+                let #name: #ty = ::vivibin::ReadDomainExt::read_fallback_args::<#ty, _>(#domain, #reader, #args)?;
+            }
+        } else if let Some(count) = &self.count {
+            let inner_ty = Self::get_vec_inner_type(ty)
+                .expect("#[count = \"...\"] attribute can only be put on fields of type Vec<T>");
+            let count_var = Self::member_var_name(count);
+            
+            quote! {
+                let #name: #ty = {
+                    let count = usize::try_from(#count_var)?;
+                    ::vivibin::ReadDomain::read_limits(&#domain).check_alloc::<#inner_ty>(count, ::vivibin::Reader::position(#reader)?)?;
+                    
+                    (0..count)
+                        .map(|_| <#inner_ty as ::vivibin::Readable<_>>::from_reader(#reader, #domain))
+                        .collect::<::anyhow::Result<_>>()?
+                };
+            }
+        } else if self.boxed {
+            match self.boxed_type() {
+                BoxedType::Box(inner_ty) => quote! {
+                    let #name: #ty = ::vivibin::ReadDomainExt::read_std_box_fallback::<#inner_ty, R>(#domain, #reader)?;
+                },
+                BoxedType::OptionBox(inner_ty) => quote! {
+                    let #name: #ty = ::vivibin::ReadDomain::read_box_nullable(#domain, #reader, |#reader| {
+                        <#inner_ty as ::vivibin::Readable<_>>::from_reader(#reader, #domain)
+                    })?.map(::std::boxed::Box::new);
+                },
+            }
+        } else {
+            match (inner_vec_type, explicit_read_impl) {
+                (None, true) => quote! {
+                    let #name: #ty = ::vivibin::CanRead::<#ty>::read(#domain, #reader)?;
+                },
+                (None, false) => match Self::get_generic_inner_type(ty, "Box") {
+                    // Box<T> can't implement Readable for every T, so it's unwrapped here
+                    Some(inner_ty) => quote! {
+                        let #name: #ty = ::std::boxed::Box::new(<#inner_ty as ::vivibin::Readable<_>>::from_reader(#reader, #domain)?);
+                    },
+                    None => quote! {
+                        let #name: #ty = ::vivibin::Readable::from_reader(#reader, #domain)?;
+                    },
+                },
+                (Some(inner_ty), true) => {
+                    *vec_required = true;
+                    quote! {
+                        let #name: #ty = ::vivibin::ReadVecExt::read_std_vec::<#inner_ty, R>(#domain, #reader)?;
+                    }
+                },
+                (Some(inner_ty), false) => {
+                    *vec_required = true;
+                    quote! {
+                        let #name: #ty = ::vivibin::ReadVecFallbackExt::read_std_vec_fallback::<#inner_ty, R>(#domain, #reader)?;
+                    }
+                },
+            }
+        };
+        
+        let tokens = match &self.endian {
+            Some(endian) => quote! {
+                let #name: #ty = {
+                    let #domain = ::vivibin::EndianAware::new(#domain, #endian);
+                    #tokens
+                    #name
+                };
             },
+            None => tokens,
+        };
+        
+        let pad = self.pad.as_ref().map(|pad| quote! {
+            let padding: usize = #pad;
+            ::vivibin::Reader::skip(#reader, padding as i64)?;
+        });
+        
+        let align = self.align.as_ref().map(|align| quote! {
+            ::vivibin::Reader::align_to(#reader, #align)?;
+        });
+        
+        let tokens = quote! {
+            #pad
+            #align
+            #tokens
         };
         
         (name, tokens)
     }
     
-    fn write_write_statement(&self, domain: &Ident, ctx: &Ident, cat: &Ident, vec_required: &mut bool, required_domain_impls: &[&Type]) -> TokenStream {
-        let NamedField { name, ty, .. } = *self;
+    /// `value` and `count_value` are expressions evaluating to a reference to the field
+    /// and to the field holding its length respectively
+    #[allow(clippy::too_many_arguments)]
+    fn write_write_statement(&self, value: &TokenStream, count_value: Option<&TokenStream>, domain: &Ident, ctx: &Ident, cat: &Ident, vec_required: &mut bool, required_domain_impls: &[&Type]) -> TokenStream {
+        let Field { ty, .. } = *self;
+        
+        if self.skip {
+            return TokenStream::new();
+        }
         
         let inner_vec_type = Self::get_vec_inner_type(ty);
         
         let explicit_write_impl = required_domain_impls.iter().copied()
             .any(|current| current == ty);
         
-        match (inner_vec_type, explicit_write_impl) {
-            (None, true) => quote! {
-                ::vivibin::CanWrite::<#cat, #ty>::write(#domain, #ctx, &self.#name)?;
-            },
-            (None, false) => quote! {
-                <#ty as ::vivibin::Writable<#cat, D>>::to_writer(&self.#name, #ctx, #domain)?;
-            },
-            (Some(inner_ty), true) => {
-                *vec_required = true;
-                quote! {
-                    ::vivibin::WriteSliceExt::write_slice::<#inner_ty>(#domain, #ctx, &self.#name)?;
+        let pad = self.pad.as_ref().map(|pad| quote! {
+            ::vivibin::write_padding(#ctx.cur_writer(), #pad)?;
+        });
+        
+        let align = self.align.as_ref().map(|align| quote! {
+            ::vivibin::WriteHeap::align_current_block(#ctx, #align)?;
+        });
+        
+        let tokens = if let Some(args) = &self.args {
+            quote! {
+                <#ty as ::vivibin::WritableWithArgs<#cat, _, _>>::to_writer_args(#value, #ctx, #domain, #args)?;
+            }
+        } else if let Some(count_value) = count_value {
+            let inner_ty = Self::get_vec_inner_type(ty)
+                .expect("#[count = \"...\"] attribute can only be put on fields of type Vec<T>");
+            let mismatch_message = format!("Length of {} does not match its count field", self.var_name().to_string().trim_start_matches('_'));
+            
+            quote! {
+                if (#value).len() != usize::try_from(*(#count_value))? {
+                    return ::core::result::Result::Err(::anyhow::anyhow!(#mismatch_message));
+                }
+                
+                <#inner_ty as ::vivibin::Writable<#cat, _>>::to_writer_slice(#value, #ctx, #domain)?;
+            }
+        } else if self.boxed {
+            match self.boxed_type() {
+                BoxedType::Box(inner_ty) => quote! {
+                    ::vivibin::WriteBoxFallbackExt::write_box_fallback::<#inner_ty>(#domain, #ctx, &**#value)?;
+                },
+                BoxedType::OptionBox(inner_ty) => quote! {
+                    ::vivibin::WriteBoxFallbackExt::write_box_nullable_fallback::<#inner_ty>(#domain, #ctx, ::core::option::Option::as_deref(#value))?;
+                },
+            }
+        } else {
+            match (inner_vec_type, explicit_write_impl) {
+                (None, true) => quote! {
+                    ::vivibin::CanWrite::<#cat, #ty>::write(#domain, #ctx, #value)?;
+                },
+                (None, false) => quote! {
+                    <#ty as ::vivibin::Writable<#cat, _>>::to_writer(#value, #ctx, #domain)?;
+                },
+                (Some(inner_ty), true) => {
+                    *vec_required = true;
+                    quote! {
+                        ::vivibin::WriteSliceExt::write_slice::<#inner_ty>(#domain, #ctx, #value)?;
+                    }
+                },
+                (Some(inner_ty), false) => {
+                    *vec_required = true;
+                    quote! {
+                        ::vivibin::WriteSliceFallbackExt::write_slice_fallback::<#inner_ty>(#domain, #ctx, #value)?;
+                    }
+                },
+            }
+        };
+        
+        let tokens = match &self.endian {
+            // see EndianAware::rewrap, domain callbacks need an EndianAware that owns its domain
+            Some(endian) if self.writes_through_domain_callbacks() => quote! {
+                {
+                    let mut endian_domain = ::vivibin::EndianAware::new(::core::clone::Clone::clone(&*#domain), #endian);
+                    {
+                        let #domain = &mut endian_domain;
+                        #tokens
+                    }
+                    *#domain = endian_domain.inner;
                 }
             },
-            (Some(inner_ty), false) => {
-                *vec_required = true;
-                quote! {
-                    ::vivibin::WriteSliceFallbackExt::write_slice_fallback::<#inner_ty>(#domain, #ctx, &self.#name)?;
+            Some(endian) => quote! {
+                {
+                    let #domain = &mut ::vivibin::EndianAware::new(&mut *#domain, #endian);
+                    #tokens
                 }
             },
+            None => tokens,
+        };
+        
+        quote! {
+            #pad
+            #align
+            #tokens
         }
     }
     
+    /// Whether the field is written through `CanWriteBox` or `CanWriteSlice`, whose callbacks hand out the domain
+    fn writes_through_domain_callbacks(&self) -> bool {
+        !self.skip && self.args.is_none() && self.count.is_none()
+            && (self.boxed || Self::get_vec_inner_type(self.ty).is_some())
+    }
+    
+    fn boxed_type(&self) -> BoxedType<'a> {
+        if let Some(inner_ty) = Self::get_generic_inner_type(self.ty, "Box") {
+            return BoxedType::Box(inner_ty);
+        }
+        
+        Self::get_generic_inner_type(self.ty, "Option")
+            .and_then(|option_inner| Self::get_generic_inner_type(option_inner, "Box"))
+            .map(BoxedType::OptionBox)
+            .expect("#[boxed] attribute can only be put on fields of type Box<T> or Option<Box<T>>")
+    }
+    
     fn get_vec_inner_type(ty: &Type) -> Option<&Type> {
+        Self::get_generic_inner_type(ty, "Vec")
+    }
+    
+    /// Returns `T` if `ty` is `wrapper<T>`
+    fn get_generic_inner_type<'t>(ty: &'t Type, wrapper: &str) -> Option<&'t Type> {
         let Type::Path(TypePath { path, .. }) = ty else {
             return None;
         };
         
         let segments = &path.segments;
-        if segments.last().is_none_or(|segment| segment.ident != "Vec") {
+        if segments.last().is_none_or(|segment| segment.ident != wrapper) {
             return None;
         }
         
@@ -105,48 +307,258 @@ impl NamedField<'_> {
     }
 }
 
-// TODO: tuple structs
-#[allow(dead_code)]
+enum BoxedType<'a> {
+    Box(&'a Type),
+    OptionBox(&'a Type),
+}
+
 enum Structure<'a> {
-    Named(Vec<NamedField<'a>>),
-    Tuple(Vec<&'a Type>),
+    Named(Vec<Field<'a>>),
+    Tuple(Vec<Field<'a>>),
+    Unit,
 }
 
 impl<'a> Structure<'a> {
-    fn required_domain_impls(&self) -> Vec<&Type> {
+    fn fields(&self) -> &[Field<'a>] {
         match self {
-            Structure::Named(named_fields) => {
-                named_fields.iter()
-                    .filter_map(|field| {
-                        field.explicit_require_domain.then_some(field.ty)
-                    })
-                    .collect()
-            },
-            Self::Tuple(_) => todo!(),
+            Self::Named(fields) | Self::Tuple(fields) => fields,
+            Self::Unit => &[],
         }
     }
     
-    fn field_names(&self) -> impl Iterator<Item = &Ident> {
+    fn required_domain_impls(&self) -> Vec<&'a Type> {
+        self.fields().iter()
+            .filter_map(|field| {
+                field.explicit_require_domain.then_some(field.ty)
+            })
+            .collect()
+    }
+    
+    /// Sums up the sizes of all fields for #[fixed_size], including their padding
+    fn fixed_size(&self) -> TokenStream {
+        let sizes = self.fields().iter()
+            .filter(|field| !field.skip)
+            .map(|field| {
+                assert!(
+                    field.align.is_none() && field.args.is_none() && !field.boxed && field.count.is_none() && field.read_if.is_none(),
+                    "#[fixed_size] cannot be combined with #[align], #[args], #[boxed], #[count] or #[read_if] fields",
+                );
+                
+                let ty = field.ty;
+                let pad = field.pad.as_ref().map(|pad| quote!((#pad) +));
+                quote!(#pad <#ty as ::vivibin::FixedSize>::SIZE)
+            });
+        
+        quote!(0 #(+ #sizes)*)
+    }
+    
+    fn var_names(&self) -> Vec<Ident> {
+        self.fields().iter()
+            .map(Field::var_name)
+            .collect()
+    }
+    
+    /// Builds `path` out of the variables declared by the read statements,
+    /// or destructures it into them when used as a pattern
+    fn construct(&self, path: &TokenStream) -> TokenStream {
+        let var_names = self.var_names();
+        
         match self {
-            Self::Named(named_fields) => {
-                named_fields.iter()
-                    .map(|field| field.name)
+            Self::Named(fields) => {
+                let members = fields.iter().map(|field| &field.member);
+                quote! { #path { #(#members: #var_names),* } }
             },
-            Self::Tuple(_) => todo!(),
+            Self::Tuple(_) => quote! { #path(#(#var_names),*) },
+            Self::Unit => quote! { #path },
+        }
+    }
+    
+    fn read_body(&self, path: &TokenStream, domain: &Ident, reader: &Ident, vec_required: &mut bool, required_domain_impls: &[&Type]) -> TokenStream {
+        let statements = self.fields().iter()
+            .map(|field| {
+                let (name, statement) = field.write_read_statement(domain, reader, vec_required, required_domain_impls);
+                
+                let condition = self.read_if_condition(field, |field| {
+                    let var_name = field.var_name();
+                    quote!(&#var_name)
+                });
+                
+                match condition {
+                    Some(condition) => {
+                        let ty = field.ty;
+                        quote! {
+                            let #name: #ty = if #condition {
+                                #statement
+                                #name
+                            } else {
+                                ::core::default::Default::default()
+                            };
+                        }
+                    },
+                    None => statement,
+                }
+            })
+            .collect::<Vec<_>>();
+        
+        let construct = self.construct(path);
+        
+        quote! {
+            #(#statements)*
+            core::result::Result::Ok(#construct)
+        }
+    }
+    
+    /// `values` maps a field to an expression evaluating to a reference to it
+    fn write_body(&self, values: impl Fn(&Field) -> TokenStream, domain: &Ident, ctx: &Ident, cat: &Ident, vec_required: &mut bool, required_domain_impls: &[&Type]) -> TokenStream {
+        let statements = self.fields().iter()
+            .map(|field| {
+                let count_value = field.count.as_ref().map(|count| {
+                    let count_field = self.fields().iter()
+                        .find(|other| other.member == *count)
+                        .unwrap();
+                    values(count_field)
+                });
+                
+                let statement = field.write_write_statement(&values(field), count_value.as_ref(), domain, ctx, cat, vec_required, required_domain_impls);
+                
+                match self.read_if_condition(field, &values) {
+                    Some(condition) => quote! {
+                        if #condition {
+                            #statement
+                        }
+                    },
+                    None => statement,
+                }
+            })
+            .collect::<Vec<_>>();
+        
+        quote! {
+            #(#statements)*
+        }
+    }
+    
+    /// Evaluates the #[read_if] condition of `field`, with the fields it references bound by name.
+    /// `values` maps a field to an expression evaluating to a reference to it
+    fn read_if_condition(&self, field: &Field, values: impl Fn(&Field) -> TokenStream) -> Option<TokenStream> {
+        let ReadIf { condition, dependencies } = field.read_if.as_ref()?;
+        
+        let bindings = dependencies.iter().map(|dependency| {
+            let dependency_field = self.fields().iter()
+                .find(|other| other.member == *dependency)
+                .unwrap();
+            let value = values(dependency_field);
+            
+            quote! {
+                let #dependency = ::core::clone::Clone::clone(#value);
+            }
+        });
+        
+        Some(quote! {
+            {
+                #(#bindings)*
+                #condition
+            }
+        })
+    }
+    
+    /// Parses #[read_if(expr)], which may only reference fields declared before it
+    fn parse_read_if(attr: &Attribute, fields: &Fields, previous_fields: &[Field]) -> ReadIf {
+        let condition: Expr = attr.parse_args().expect("Expected expression in #[read_if(...)] attribute");
+        
+        let mut dependencies = Vec::new();
+        Self::collect_idents(quote!(#condition), &mut |ident| {
+            let Some(field_ident) = fields.iter().filter_map(|field| field.ident.as_ref()).find(|field_ident| *field_ident == ident) else {
+                return;
+            };
+            
+            let member = Member::Named(field_ident.clone());
+            if !previous_fields.iter().any(|field| field.member == member) {
+                panic!("Field {ident} referenced by #[read_if] has to be declared before the conditional field");
+            }
+            
+            if !dependencies.contains(&member) {
+                dependencies.push(member);
+            }
+        });
+        
+        ReadIf { condition, dependencies }
+    }
+    
+    /// Calls `callback` with every identifier in `tokens` that isn't a method or field access
+    fn collect_idents(tokens: TokenStream, callback: &mut impl FnMut(&Ident)) {
+        let mut after_dot = false;
+        
+        for token in tokens {
+            match &token {
+                TokenTree::Ident(ident) if !after_dot => callback(ident),
+                TokenTree::Group(group) => Self::collect_idents(group.stream(), callback),
+                _ => {},
+            }
+            
+            after_dot = matches!(&token, TokenTree::Punct(punct) if punct.as_char() == '.');
         }
     }
     
-    fn from_syn_struct(data: &'a DataStruct) -> Self {
-        let mut fields = Vec::new();
+    /// Parses #[count = "field"], which has to refer to a field declared before it
+    fn parse_count(attr: &Attribute, previous_fields: &[Field]) -> Member {
+        let Meta::NameValue(name_value) = &attr.meta else {
+            panic!("Expected #[count = \"field\"] attribute");
+        };
+        
+        let Expr::Lit(ExprLit { lit: Lit::Str(field_name), .. }) = &name_value.value else {
+            panic!("Expected string literal in #[count = \"field\"] attribute");
+        };
+        
+        let member: Member = field_name.parse()
+            .expect("Expected field name in #[count = \"field\"] attribute");
+        
+        if !previous_fields.iter().any(|field| field.member == member) {
+            panic!("Field {:?} referenced by #[count] has to be declared before the Vec", field_name.value());
+        }
+        
+        member
+    }
+    
+    /// Parses #[endian(big)] or #[endian(little)] into an Endianness value
+    fn parse_endian(attr: &Attribute) -> TokenStream {
+        let endian: Ident = attr.parse_args().expect("Expected big or little in #[endian(...)] attribute");
+        
+        match endian.to_string().as_str() {
+            "big" => quote!(::vivibin::Endianness::Big),
+            "little" => quote!(::vivibin::Endianness::Little),
+            "native" => quote!(::vivibin::Endianness::Native),
+            _ => panic!("Expected big, little or native in #[endian(...)] attribute, got {endian}"),
+        }
+    }
+    
+    fn from_syn_fields(fields: &'a Fields) -> Self {
+        let mut result = Vec::new();
         
         let boxed_ident = Ident::new("boxed", Span::call_site());
         let require_domain_ident = Ident::new("require_domain", Span::call_site());
+        let pad_ident = Ident::new("pad", Span::call_site());
+        let align_ident = Ident::new("align", Span::call_site());
+        let args_ident = Ident::new("args", Span::call_site());
+        let skip_ident = Ident::new("skip", Span::call_site());
+        let count_ident = Ident::new("count", Span::call_site());
+        let endian_ident = Ident::new("endian", Span::call_site());
+        let read_if_ident = Ident::new("read_if", Span::call_site());
         
-        for field in &data.fields {
-            let field_name = field.ident.as_ref().expect("Expected named field");
-            
+        for (index, field) in fields.iter().enumerate() {
+            let member = match &field.ident {
+                Some(ident) => Member::Named(ident.clone()),
+                None => Member::Unnamed(index.into()),
+            };
             
             let mut explicit_require_domain = false;
+            let mut pad = None;
+            let mut align = None;
+            let mut args = None;
+            let mut skip = false;
+            let mut boxed = false;
+            let mut count = None;
+            let mut endian = None;
+            let mut read_if = None;
             for attr in &field.attrs {
                 let Some(ident) = attr.path().get_ident() else {
                     continue;
@@ -155,38 +567,220 @@ impl<'a> Structure<'a> {
                 if *ident == require_domain_ident {
                     explicit_require_domain = true;
                 } else if *ident == boxed_ident {
-                    panic!("#[boxed] attribute on a field is not supported yet!");
+                    boxed = true;
+                } else if *ident == pad_ident {
+                    pad = Some(attr.parse_args().expect("Expected byte count in #[pad(...)] attribute"));
+                } else if *ident == align_ident {
+                    align = Some(attr.parse_args().expect("Expected alignment in #[align(...)] attribute"));
+                } else if *ident == args_ident {
+                    args = Some(attr.parse_args().expect("Expected expression in #[args(...)] attribute"));
+                } else if *ident == skip_ident {
+                    skip = true;
+                } else if *ident == count_ident {
+                    count = Some(Self::parse_count(attr, &result));
+                } else if *ident == endian_ident {
+                    endian = Some(Self::parse_endian(attr));
+                } else if *ident == read_if_ident {
+                    read_if = Some(Self::parse_read_if(attr, fields, &result));
                 }
             }
             
             let field_type = &field.ty;
-            fields.push(NamedField {
-                name: field_name,
+            result.push(Field {
+                member,
                 ty: field_type,
                 explicit_require_domain,
+                pad,
+                align,
+                args,
+                boxed,
+                count,
+                endian,
+                skip,
+                read_if,
+            });
+        }
+        
+        match fields {
+            Fields::Named(_) => Self::Named(result),
+            Fields::Unnamed(_) => Self::Tuple(result),
+            Fields::Unit => Self::Unit,
+        }
+    }
+}
+
+struct Variant<'a> {
+    ident: &'a Ident,
+    tag_const: Ident,
+    discriminant: Option<&'a Expr>,
+    structure: Structure<'a>,
+}
+
+enum TypeBody<'a> {
+    Struct(Structure<'a>),
+    Enum {
+        tag_type: Box<Type>,
+        variants: Vec<Variant<'a>>,
+    },
+}
+
+impl<'a> TypeBody<'a> {
+    fn from_syn(name: &Ident, attrs: &[Attribute], data: &'a Data) -> Self {
+        match data {
+            Data::Struct(data) => Self::Struct(Structure::from_syn_fields(&data.fields)),
+            Data::Enum(data) => Self::from_syn_enum(attrs, data),
+            Data::Union(_) => panic!("Expected {name} to be a struct or an enum"),
+        }
+    }
+    
+    fn from_syn_enum(attrs: &[Attribute], data: &'a DataEnum) -> Self {
+        let tag_ident = Ident::new("tag", Span::call_site());
+        
+        let mut tag_type: Box<Type> = parse_quote!(u32);
+        
+        for attr in attrs {
+            if attr.path().get_ident().is_some_and(|ident| *ident == tag_ident) {
+                tag_type = attr.parse_args()
+                    .expect("Expected integer type in #[tag(...)] attribute");
+            }
+        }
+        
+        let variants = data.variants.iter().enumerate()
+            .map(|(index, variant)| Variant {
+                ident: &variant.ident,
+                tag_const: format_ident!("TAG_{}", index),
+                discriminant: variant.discriminant.as_ref().map(|(_, expr)| expr),
+                structure: Structure::from_syn_fields(&variant.fields),
+            })
+            .collect();
+        
+        Self::Enum { tag_type, variants }
+    }
+    
+    fn structures(&self) -> Vec<&Structure<'a>> {
+        match self {
+            Self::Struct(structure) => vec![structure],
+            Self::Enum { variants, .. } => variants.iter().map(|variant| &variant.structure).collect(),
+        }
+    }
+    
+    fn required_domain_impls(&self) -> Vec<&'a Type> {
+        let mut result: Vec<&Type> = Vec::new();
+        
+        for ty in self.structures().into_iter().flat_map(Structure::required_domain_impls) {
+            if !result.contains(&ty) {
+                result.push(ty);
+            }
+        }
+        
+        result
+    }
+    
+    fn read_body(&self, name: &Ident, domain: &Ident, reader: &Ident, vec_required: &mut bool, required_domain_impls: &[&Type]) -> TokenStream {
+        match self {
+            TypeBody::Struct(structure) => {
+                structure.read_body(&quote!(#name), domain, reader, vec_required, required_domain_impls)
+            },
+            TypeBody::Enum { tag_type, variants } => {
+                let tag_consts = TypeBody::tag_consts(tag_type, variants);
+                let unknown_tag_message = format!("Unknown tag {{tag}} for enum {name} (at offset 0x{{offset:x}})");
+                
+                let arms = variants.iter()
+                    .map(|variant| {
+                        let Variant { ident, tag_const, structure, .. } = variant;
+                        let body = structure.read_body(&quote!(#name::#ident), domain, reader, vec_required, required_domain_impls);
+                        
+                        quote! {
+                            #tag_const => {
+                                #body
+                            },
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                
+                quote! {
+                    #tag_consts
+                    
+                    let offset = ::vivibin::Reader::position(#reader)?;
+                    let tag: #tag_type = ::vivibin::Readable::from_reader(#reader, #domain)?;
+                    
+                    match tag {
+                        #(#arms)*
+                        _ => core::result::Result::Err(::anyhow::anyhow!(#unknown_tag_message)),
+                    }
+                }
+            },
+        }
+    }
+    
+    /// Types of the fields that can be initialized with Default on read, through #[skip] or #[read_if]
+    fn skipped_types(&self) -> Vec<&'a Type> {
+        let mut result: Vec<&Type> = Vec::new();
+        
+        let skipped_fields = self.structures().into_iter()
+            .flat_map(Structure::fields)
+            .filter(|field| field.skip || field.read_if.is_some());
+        
+        for field in skipped_fields {
+            if !result.contains(&field.ty) {
+                result.push(field.ty);
+            }
+        }
+        
+        result
+    }
+    
+    /// Declares one constant per variant holding its tag, counting up from the
+    /// previous variant like Rust's own discriminants do
+    fn tag_consts(tag_type: &Type, variants: &[Variant]) -> TokenStream {
+        let mut previous: Option<&Ident> = None;
+        let mut consts = Vec::new();
+        
+        for variant in variants {
+            let tag_const = &variant.tag_const;
+            let value = match (variant.discriminant, previous) {
+                (Some(discriminant), _) => quote! { #discriminant },
+                (None, Some(previous)) => quote! { #previous + 1 },
+                (None, None) => quote! { 0 },
+            };
+            
+            consts.push(quote! {
+                const #tag_const: #tag_type = #value;
             });
+            previous = Some(tag_const);
         }
         
-        Self::Named(fields)
+        quote! {
+            #(#consts)*
+        }
+    }
+}
+
+/// Parses #[magic(b"...")] or #[magic("...")] into a byte string literal
+fn parse_magic(attr: &Attribute) -> LitByteStr {
+    match attr.parse_args().expect("Expected string literal in #[magic(...)] attribute") {
+        Lit::ByteStr(bytes) => bytes,
+        Lit::Str(string) => LitByteStr::new(string.value().as_bytes(), string.span()),
+        _ => panic!("Expected string literal in #[magic(...)] attribute"),
     }
 }
 
-#[proc_macro_derive(Readable, attributes(require_domain, boxed, extra_read_domain_deps))]
+#[proc_macro_derive(Readable, attributes(require_domain, boxed, extra_read_domain_deps, tag, pad, align, args, skip, magic, count, endian, read_if, fixed_size))]
 pub fn derive_readable(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     let input = parse_macro_input!(input as DeriveInput);
     
     let name = input.ident;
     
-    let Data::Struct(data) = input.data else {
-        panic!("Expected {name} to be a struct")
-    };
-    
     let boxed_ident = Ident::new("boxed", Span::call_site());
     let require_domain_ident = Ident::new("require_domain", Span::call_site());
     let extra_read_domain_deps_ident = Ident::new("extra_read_domain_deps", Span::call_site());
+    let magic_ident = Ident::new("magic", Span::call_site());
+    let fixed_size_ident = Ident::new("fixed_size", Span::call_site());
     
     let mut is_boxed = false;
     let mut extra_read_domain_deps = None;
+    let mut magic = None;
+    let mut is_fixed_size = false;
     
     for attr in &input.attrs {
         let Some(ident) = attr.path().get_ident() else {
@@ -201,36 +795,45 @@ pub fn derive_readable(input: proc_macro::TokenStream) -> proc_macro::TokenStrea
             };
             
             extra_read_domain_deps = Some(&list.tokens);
+        } else if *ident == magic_ident {
+            magic = Some(parse_magic(attr));
+        } else if *ident == fixed_size_ident {
+            is_fixed_size = true;
         } else if *ident == require_domain_ident {
             panic!("#[require_domain] attribute cannot be put on a type definition!");
         }
     }
     
-    let structure = Structure::from_syn_struct(&data);
+    let type_body = TypeBody::from_syn(&name, &input.attrs, &input.data);
+    
+    let fixed_size_def = is_fixed_size.then(|| {
+        let TypeBody::Struct(structure) = &type_body else {
+            panic!("#[fixed_size] can only be put on structs");
+        };
+        
+        assert!(!is_boxed, "#[fixed_size] cannot be combined with #[boxed]");
+        
+        let magic_size = magic.as_ref().map_or(0, |magic| magic.value().len());
+        let fields_size = structure.fixed_size();
+        
+        quote! {
+            impl #name {
+                pub const SIZE: usize = #magic_size + #fields_size;
+            }
+            
+            impl ::vivibin::FixedSize for #name {
+                const SIZE: usize = Self::SIZE;
+            }
+        }
+    });
     
     let domain = Ident::new("domain", Span::call_site());
     let reader = Ident::new("reader", Span::call_site());
     
-    let required_domain_impls: Vec<&Type> = structure.required_domain_impls();
+    let required_domain_impls: Vec<&Type> = type_body.required_domain_impls();
     let mut vec_required = false;
     
-    let body = match &structure {
-        Structure::Named(named_fields) => {
-            let field_names = structure.field_names();
-            
-            let (var_names, statements) = named_fields.iter()
-                .map(|field| field.write_read_statement(&domain, &reader, &mut vec_required, &required_domain_impls))
-                .unzip::<_, _, Vec<Ident>, Vec<TokenStream>>();
-            
-            quote! {
-                #(#statements)*
-                core::result::Result::Ok(#name {
-                    #(#field_names: #var_names),*
-                })
-            }
-        },
-        Structure::Tuple(_) => todo!(),
-    };
+    let body = type_body.read_body(&name, &domain, &reader, &mut vec_required, &required_domain_impls);
     
     let constraint = match (required_domain_impls.is_empty(), vec_required) {
         (true, true) => quote! { ::vivibin::CanReadVec },
@@ -242,47 +845,130 @@ pub fn derive_readable(input: proc_macro::TokenStream) -> proc_macro::TokenStrea
     let extra_read_domain_deps = extra_read_domain_deps
         .map_or_else(TokenStream::new, |value| quote!(+ #value));
     
-    let from_reader_def = if is_boxed {
+    let skipped_types = type_body.skipped_types();
+    let where_clause = if skipped_types.is_empty() {
+        TokenStream::new()
+    } else {
+        quote! { where #(#skipped_types: ::core::default::Default),* }
+    };
+    
+    let magic = magic.map(|magic| quote! {
+        ::vivibin::Reader::expect_magic(reader, #magic)?;
+    });
+    
+    let boxed_def = if is_boxed {
         quote! {
-            fn from_reader<R: ::vivibin::Reader>(reader: &mut R, domain: D) -> ::anyhow::Result<Self> {
-                ::vivibin::ReadDomainExt::read_box(domain, reader, |reader| {
-                    Self::from_reader_unboxed(reader, domain)
-                })
-            }
+            const BOXED: bool = true;
         }
     } else {
         quote! {}
     };
     
     quote! {
-        impl<D: #constraint #extra_read_domain_deps> ::vivibin::Readable<D> for #name {
+        impl<D: #constraint #extra_read_domain_deps> ::vivibin::Readable<D> for #name #where_clause {
+            #boxed_def
+            
             fn from_reader_unboxed<R: ::vivibin::Reader>(
                 reader: &mut R,
                 domain: D
             ) -> ::anyhow::Result<Self> {
+                #magic
                 #body
             }
-            
-            #from_reader_def
         }
+        
+        #fixed_size_def
     }.into()
 }
 
-#[proc_macro_derive(Writable, attributes(require_domain, extra_write_domain_deps))]
-pub fn derive_writable(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+#[proc_macro_derive(ReadableWithArgs, attributes(args_type, tag, pad, align, args, skip, magic, count, endian, read_if))]
+pub fn derive_readable_with_args(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     let input = parse_macro_input!(input as DeriveInput);
     
     let name = input.ident;
     
-    let Data::Struct(data) = input.data else {
-        panic!("Expected {name} to be a struct")
+    let args_type_ident = Ident::new("args_type", Span::call_site());
+    let magic_ident = Ident::new("magic", Span::call_site());
+    
+    let mut args_type: Option<Type> = None;
+    let mut magic = None;
+    
+    for attr in &input.attrs {
+        let Some(ident) = attr.path().get_ident() else {
+            continue;
+        };
+        
+        if *ident == args_type_ident {
+            args_type = Some(attr.parse_args().expect("Expected type in #[args_type(...)] attribute"));
+        } else if *ident == magic_ident {
+            magic = Some(parse_magic(attr));
+        }
+    }
+    
+    let args_type = args_type.unwrap_or_else(|| panic!("Expected #[args_type(...)] attribute on {name}"));
+    let type_body = TypeBody::from_syn(&name, &input.attrs, &input.data);
+    
+    let domain = Ident::new("domain", Span::call_site());
+    let reader = Ident::new("reader", Span::call_site());
+    
+    // ReadableWithArgs has to work with every domain, so there can't be any extra requirements
+    let required_domain_impls: Vec<&Type> = type_body.required_domain_impls();
+    let mut vec_required = false;
+    
+    let body = type_body.read_body(&name, &domain, &reader, &mut vec_required, &required_domain_impls);
+    
+    if vec_required || !required_domain_impls.is_empty() {
+        panic!("ReadableWithArgs can't be derived for {name} because it contains Vec or #[require_domain] fields");
+    }
+    
+    let skipped_types = type_body.skipped_types();
+    let where_clause = if skipped_types.is_empty() {
+        TokenStream::new()
+    } else {
+        quote! { where #(#skipped_types: ::core::default::Default),* }
     };
     
+    let magic = magic.map(|magic| quote! {
+        ::vivibin::Reader::expect_magic(reader, #magic)?;
+    });
+    
+    // the inner function gives the reader and domain types names, which some field reads need
+    quote! {
+        impl ::vivibin::ReadableWithArgs<#args_type> for #name #where_clause {
+            fn from_reader_args(
+                reader: &mut impl ::vivibin::Reader,
+                domain: impl ::vivibin::ReadDomain,
+                args: #args_type
+            ) -> ::anyhow::Result<Self> {
+                #[allow(unused_variables)]
+                fn read_with_args<R: ::vivibin::Reader, D: ::vivibin::ReadDomain>(
+                    reader: &mut R,
+                    domain: D,
+                    args: #args_type
+                ) -> ::anyhow::Result<#name> {
+                    #magic
+                    #body
+                }
+                
+                read_with_args(reader, domain, args)
+            }
+        }
+    }.into()
+}
+
+#[proc_macro_derive(Writable, attributes(require_domain, boxed, extra_write_domain_deps, tag, pad, align, args, skip, magic, count, endian, read_if))]
+pub fn derive_writable(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    
+    let name = input.ident;
+    
     let boxed_ident = Ident::new("boxed", Span::call_site());
     let require_domain_ident = Ident::new("require_domain", Span::call_site());
     let extra_write_domain_deps_ident = Ident::new("extra_write_domain_deps", Span::call_site());
+    let magic_ident = Ident::new("magic", Span::call_site());
     
     let mut extra_write_domain_deps = None;
+    let mut magic = None;
     
     for attr in &input.attrs {
         let Some(ident) = attr.path().get_ident() else {
@@ -297,32 +983,65 @@ pub fn derive_writable(input: proc_macro::TokenStream) -> proc_macro::TokenStrea
             };
             
             extra_write_domain_deps = Some(&list.tokens);
+        } else if *ident == magic_ident {
+            magic = Some(parse_magic(attr));
         } else if *ident == require_domain_ident {
             panic!("#[require_domain] attribute cannot be put on a type definition!");
         }
     }
     
-    let structure = Structure::from_syn_struct(&data);
+    let type_body = TypeBody::from_syn(&name, &input.attrs, &input.data);
     
     let domain = Ident::new("domain", Span::call_site());
     let reader = Ident::new("ctx", Span::call_site());
     
     let cat: Ident = Ident::new("Cat", Span::call_site());
     
-    let required_domain_impls: Vec<&Type> = structure.required_domain_impls();
+    let required_domain_impls: Vec<&Type> = type_body.required_domain_impls();
     let mut vec_required = false;
     
-    let body = match &structure {
-        Structure::Named(named_fields) => {
-            let statements = named_fields.iter()
-                .map(|field| field.write_write_statement(&domain, &reader, &cat, &mut vec_required, &required_domain_impls))
+    let body = match &type_body {
+        TypeBody::Struct(structure) => {
+            structure.write_body(
+                |field| {
+                    let member = &field.member;
+                    quote!(&self.#member)
+                },
+                &domain, &reader, &cat, &mut vec_required, &required_domain_impls,
+            )
+        },
+        TypeBody::Enum { tag_type, variants } => {
+            let tag_consts = TypeBody::tag_consts(tag_type, variants);
+            
+            let arms = variants.iter()
+                .map(|variant| {
+                    let Variant { ident, tag_const, structure, .. } = variant;
+                    let pattern = structure.construct(&quote!(Self::#ident));
+                    let body = structure.write_body(
+                        |field| {
+                            let var_name = field.var_name();
+                            quote!(#var_name)
+                        },
+                        &domain, &reader, &cat, &mut vec_required, &required_domain_impls,
+                    );
+                    
+                    quote! {
+                        #pattern => {
+                            <#tag_type as ::vivibin::Writable<#cat, D>>::to_writer(&#tag_const, ctx, domain)?;
+                            #body
+                        },
+                    }
+                })
                 .collect::<Vec<_>>();
             
             quote! {
-                #(#statements)*
+                #tag_consts
+                
+                match self {
+                    #(#arms)*
+                }
             }
         },
-        Structure::Tuple(_) => todo!(),
     };
     
     let constraint = match (required_domain_impls.is_empty(), vec_required) {
@@ -332,15 +1051,127 @@ pub fn derive_writable(input: proc_macro::TokenStream) -> proc_macro::TokenStrea
         (false, false) => quote! { #(::vivibin::CanWrite<#cat, #required_domain_impls>)+* },
     };
     
+    let box_required = type_body.structures().into_iter()
+        .flat_map(Structure::fields)
+        .any(|field| field.boxed);
+    
+    let box_constraint = box_required.then(|| quote!(+ ::vivibin::CanWriteBox<#cat>));
+    
+    let clone_required = type_body.structures().into_iter()
+        .flat_map(Structure::fields)
+        .any(|field| field.endian.is_some() && field.writes_through_domain_callbacks());
+    
+    let clone_constraint = clone_required.then(|| quote!(+ ::core::clone::Clone));
+    
     let extra_write_domain_deps = extra_write_domain_deps
         .map_or_else(TokenStream::new, |value| quote!(+ #value));
     
+    let magic = magic.map(|magic| quote! {
+        ::std::io::Write::write_all(ctx.cur_writer(), #magic)?;
+    });
+    
     quote! {
-        impl<#cat: ::vivibin::HeapCategory, D: #constraint #extra_write_domain_deps> ::vivibin::Writable<#cat, D> for #name {
+        impl<#cat: ::vivibin::HeapCategory, D: #constraint #box_constraint #clone_constraint #extra_write_domain_deps> ::vivibin::Writable<#cat, D> for #name {
             fn to_writer_unboxed(&self, ctx: &mut impl ::vivibin::WriteCtx<#cat>, domain: &mut D) -> ::anyhow::Result<()> {
+                #magic
                 #body
                 Ok(())
             }
         }
     }.into()
 }
+
+fn debug_value_of_fields(fields: &Fields, values: &[TokenStream]) -> TokenStream {
+    match fields {
+        Fields::Named(named) => {
+            let entries = named.named.iter().zip(values)
+                .map(|(field, value)| {
+                    let key = field.ident.as_ref().unwrap().to_string();
+                    let key = key.strip_prefix("r#").unwrap_or(&key);
+                    quote! {
+                        (::std::string::String::from(#key), ::vivibin::debug_value::ToDebugValue::to_debug_value(#value))
+                    }
+                });
+            
+            quote! {
+                ::vivibin::debug_value::DebugValue::Map(::std::vec![#(#entries),*])
+            }
+        },
+        Fields::Unnamed(_) => quote! {
+            ::vivibin::debug_value::DebugValue::Seq(::std::vec![#(::vivibin::debug_value::ToDebugValue::to_debug_value(#values)),*])
+        },
+        Fields::Unit => quote! {
+            ::vivibin::debug_value::DebugValue::Null
+        },
+    }
+}
+
+#[proc_macro_derive(VivibinDebug)]
+pub fn derive_vivibin_debug(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    
+    let name = input.ident;
+    
+    let body = match &input.data {
+        Data::Struct(data) => {
+            let values = data.fields.members()
+                .map(|member| quote!(&self.#member))
+                .collect::<Vec<_>>();
+            
+            debug_value_of_fields(&data.fields, &values)
+        },
+        Data::Enum(data) => {
+            let arms = data.variants.iter()
+                .map(|variant| {
+                    let ident = &variant.ident;
+                    let variant_name = ident.to_string();
+                    
+                    let var_names = variant.fields.members()
+                        .map(|member| Field::member_var_name(&member))
+                        .collect::<Vec<_>>();
+                    let values = var_names.iter()
+                        .map(|var_name| quote!(#var_name))
+                        .collect::<Vec<_>>();
+                    
+                    let pattern = match &variant.fields {
+                        Fields::Named(named) => {
+                            let members = named.named.iter().map(|field| &field.ident);
+                            quote!(Self::#ident { #(#members: #var_names),* })
+                        },
+                        Fields::Unnamed(_) => quote!(Self::#ident(#(#var_names),*)),
+                        Fields::Unit => quote!(Self::#ident),
+                    };
+                    
+                    // unit variants are just their name, others are a map of the name to their fields
+                    let value = if variant.fields.is_empty() {
+                        quote!(::vivibin::debug_value::DebugValue::Str(::std::string::String::from(#variant_name)))
+                    } else {
+                        let fields = debug_value_of_fields(&variant.fields, &values);
+                        quote! {
+                            ::vivibin::debug_value::DebugValue::Map(::std::vec![(::std::string::String::from(#variant_name), #fields)])
+                        }
+                    };
+                    
+                    quote! {
+                        #pattern => #value,
+                    }
+                })
+                .collect::<Vec<_>>();
+            
+            quote! {
+                match self {
+                    #(#arms)*
+                }
+            }
+        },
+        Data::Union(_) => panic!("Expected {name} to be a struct or an enum"),
+    };
+    
+    quote! {
+        impl ::vivibin::debug_value::ToDebugValue for #name {
+            fn to_debug_value(&self) -> ::vivibin::debug_value::DebugValue {
+                #body
+            }
+        }
+    }.into()
+}